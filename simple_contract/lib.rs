@@ -2,10 +2,43 @@
 
 #[ink::contract]
 mod simple_contract {
-    use ink::storage::Mapping;
+    #[cfg(not(test))]
+    use ink::env::call::{build_call, ExecutionInput, Selector};
+    use ink::env::hash::Blake2x256;
+    #[cfg(not(test))]
+    use ink::env::DefaultEnvironment;
+    use ink::prelude::{string::String, vec::Vec};
+    use ink::storage::{Lazy, Mapping};
     use scale::{Decode, Encode};
 
-    type TokenId = u32;
+    /// Address of the PSP22 contract of a token.
+    type TokenId = AccountId;
+    type PoolId = u32;
+
+    /// Key of an account's balance of a token within a pool.
+    type BalanceKey = (AccountId, PoolId, TokenId);
+
+    /// Scaling factor for prices expressed as fixed-point numbers.
+    const PRECISION: Balance = 1_000_000_000_000;
+
+    /// Swap fee in basis points of the pool created by the `default` constructor.
+    const FEE_BPS: u16 = 30;
+
+    /// Minimum number of blocks between committing to a swap and revealing it.
+    const COMMIT_REVEAL_DELAY: BlockNumber = 1;
+
+    /// Largest number of decimals accepted for a token.
+    const MAX_DECIMALS: u8 = 36;
+
+    /// Approximate number of blocks per year, assuming 6 second blocks.
+    const BLOCKS_PER_YEAR: Balance = 5_256_000;
+
+    /// Assumed block time in seconds, matching `BLOCKS_PER_YEAR`.
+    const SECONDS_PER_BLOCK: u32 = 6;
+
+    /// Default LP tokens of a pool's first deposit locked to the zero address, so the share
+    /// price cannot be inflated from a dust supply.
+    const MINIMUM_LIQUIDITY: Balance = 1_000;
 
     #[derive(Decode, Encode, Copy, Clone, Debug)]
     #[cfg_attr(
@@ -15,22 +48,263 @@ mod simple_contract {
     pub struct AmmPool {
         pub token_0: TokenId,
         pub token_1: TokenId,
+        /// Swap fee in basis points, deducted from the input amount.
+        pub fee_bps: u16,
+    }
+
+    /// Tokens, reserves and LP supply of a pool, read at once.
+    #[derive(Decode, Encode, Copy, Clone, Debug)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct PoolInfo {
+        pub token_0: TokenId,
+        pub token_1: TokenId,
+        pub reserve_0: Balance,
+        pub reserve_1: Balance,
+        pub total_lp_supply: Balance,
+    }
+
+    #[derive(Decode, Encode, Debug, PartialEq, Eq)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum Error {
+        /// Deposit amounts do not match the pool's reserve ratio.
+        RatioMismatch { optimal_amount_1: Balance },
+        /// Both pool tokens are the same.
+        IdenticalTokens,
+        /// No price was recorded at or before the requested block.
+        NoCheckpoint,
+        /// Token does not belong to the pool.
+        TokenNotInPool,
+        /// Caller has no liquidity in the pool.
+        NoLiquidity,
+        /// Withdrawal exceeds the caller's position.
+        InsufficientBalance,
+        /// Pool reserves cannot cover the withdrawal or swap output.
+        InsufficientReserve,
+        /// Contract is frozen by the guardian.
+        EmergencyPaused,
+        /// Caller exceeded the per-block swap limit.
+        RateLimited,
+        /// Swap output truncates to zero.
+        ZeroOutput,
+        /// Transaction executed after its deadline.
+        Expired,
+        /// Deposit amount fell below the requested minimum.
+        InsufficientAmount,
+        /// First deposit is missing one of the tokens.
+        InvalidInitialRatio,
+        /// Execution price exceeds the requested limit.
+        PriceLimitExceeded,
+        /// Swap output fell below the requested minimum.
+        SlippageExceeded,
+        /// Caller has no pending swap commitment.
+        NoCommitment,
+        /// Revealed swap does not match the commitment.
+        CommitmentMismatch,
+        /// Swap revealed before the commit-reveal delay elapsed.
+        RevealTooEarly,
+        /// Swap split into zero chunks, or into more chunks than units of input.
+        InvalidChunks,
+        /// Deposit is too small to mint any LP tokens.
+        InsufficientLiquidityMinted,
+        /// Withdrawal is too small to return any tokens.
+        InsufficientLiquidityBurned,
+        /// Arithmetic overflowed or underflowed `Balance`.
+        Overflow,
+        /// No pool exists with the given id.
+        PoolNotFound,
+        /// A pool for the token pair already exists.
+        PoolExists { pool_id: PoolId },
+        /// Exact-output swap needs more input than the given maximum.
+        ExcessiveInput,
+        /// Swap fee is not below 100%.
+        InvalidFee,
+        /// A PSP22 or native transfer to or from the contract failed.
+        TransferFailed,
+        /// Caller is not the owner.
+        NotOwner,
+        /// Swaps and deposits are halted by the owner.
+        Paused,
+        /// Message was called again while a previous call was still executing.
+        Reentrancy,
+        /// Swap would decrease the product of the pool's reserves.
+        InvariantViolation,
+        /// Recorded prices do not cover the requested window.
+        InsufficientHistory,
+        /// Flash swap callback did not return enough tokens to keep the reserve product.
+        FlashRepaymentInsufficient,
+        /// Flash swap callback contract call failed.
+        CallbackFailed,
+        /// Swap path is shorter than two tokens or a consecutive pair has no pool.
+        NoRoute,
+        /// Swap input is nothing once the fee is deducted.
+        AmountTooSmall,
+        /// Caller is not the guardian.
+        NotGuardian,
+        /// Guardian would be the same account as the owner.
+        GuardianIsOwner,
+        /// Token decimals exceed `MAX_DECIMALS`.
+        InvalidDecimals { token: TokenId },
+        /// Rescue exceeds the free native balance above the existential deposit.
+        InsufficientNativeBalance { free_balance: Balance },
+        /// Per-hop minimums are given but not one for each hop of the route.
+        InvalidMinOuts,
+        /// Output of the given route hop, counted from zero, fell below its minimum.
+        HopSlippageExceeded { hop: u32 },
+    }
+
+    /// Error returned by PSP22 token contracts, as defined by the standard.
+    #[derive(Decode, Encode, Debug, PartialEq, Eq)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum PSP22Error {
+        Custom(String),
+        InsufficientBalance,
+        InsufficientAllowance,
+        ZeroRecipientAddress,
+        ZeroSenderAddress,
+        SafeTransferCheckFailed(String),
+    }
+
+    /// Tunable parameters of the contract and of one of its pools.
+    #[derive(Decode, Encode, Copy, Clone, Debug, PartialEq, Eq)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct Config {
+        pub swap_rate_limit: u32,
+        pub paused: bool,
+        pub emergency_paused: bool,
+        pub owner: AccountId,
+        pub guardian: Option<AccountId>,
+        pub protocol_fee_recipient: AccountId,
+        pub protocol_fee_bps: u16,
+        pub min_initial_liquidity: Balance,
+        pub fee_bps: u16,
+    }
+
+    /// Snapshot of the cumulative fees accrued by the pool at a given block.
+    #[derive(Decode, Encode, Copy, Clone, Debug, Default)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct FeeCheckpoint {
+        pub block: BlockNumber,
+        pub fees_0: Balance,
+        pub fees_1: Balance,
+    }
+
+    /// Spot price of token_0 in token_1 recorded at a given block.
+    #[derive(Decode, Encode, Copy, Clone, Debug)]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout)
+    )]
+    pub struct PriceCheckpoint {
+        pub block: BlockNumber,
+        pub price: Balance,
+    }
+
+    /// An account's position and activity in a pool.
+    #[derive(Decode, Encode, Copy, Clone, Debug, PartialEq, Eq)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct AccountSummary {
+        pub lp_balance: Balance,
+        /// Share of the LP token supply in basis points.
+        pub share_bps: u32,
+        pub balance_0: Balance,
+        pub balance_1: Balance,
+        /// Swaps performed across all pools.
+        pub swap_count: u64,
+    }
+
+    /// Outcome of the internal consistency checks of a pool.
+    #[derive(Decode, Encode, Copy, Clone, Debug, PartialEq, Eq)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct InvariantReport {
+        /// The square root of `reserve_0 * reserve_1` covers the LP token supply.
+        pub k_consistent: bool,
+        /// The LP balances of all accounts, including the locked minimum liquidity, add up to
+        /// the LP token supply.
+        pub shares_consistent: bool,
+        /// The contract holds enough of each pool token to cover the reserves and unpaid
+        /// fees of every pool trading it.
+        pub reserves_backed: bool,
+        /// Unpaid protocol and LP fees and the fee checkpoint do not exceed the fees ever
+        /// accrued, so no derived amount would be negative.
+        pub no_negative_implied: bool,
+    }
+
+    #[ink(event)]
+    pub struct PoolCreated {
+        pool_id: PoolId,
+        tokens: (TokenId, TokenId),
     }
 
     #[ink(event)]
     pub struct Swapped {
+        pool_id: PoolId,
         token_in: TokenId,
         token_out: TokenId,
         token_in_amount: Balance,
         token_out_amount: Balance,
         account: AccountId,
+        /// Post-trade price of `token_in` in `token_out`, scaled by `PRECISION`.
+        new_spot_price: Balance,
     }
 
     #[ink(event)]
     pub struct LiquidityAdded {
+        pool_id: PoolId,
+        tokens: (TokenId, TokenId),
+        amounts: (Balance, Balance),
+        account: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct LiquidityRemoved {
+        pool_id: PoolId,
         tokens: (TokenId, TokenId),
         amounts: (Balance, Balance),
         account: AccountId,
+        /// Account the withdrawn amounts are paid out to.
+        recipient: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct FeesClaimed {
+        pool_id: PoolId,
+        account: AccountId,
+        /// LP fees of token_0 and token_1 paid out with the withdrawal.
+        amounts: (Balance, Balance),
+        recipient: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct FeesReset {
+        pool_id: PoolId,
+        token: TokenId,
+        previous: Balance,
+    }
+
+    #[ink(event)]
+    pub struct FeesWithdrawn {
+        pool_id: PoolId,
+        token: TokenId,
+        amount: Balance,
+        recipient: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct Skimmed {
+        token: TokenId,
+        amount: Balance,
+        recipient: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct Paused {
+        reason: u8,
     }
 
     /// Defines the storage of your contract.
@@ -38,189 +312,5670 @@ mod simple_contract {
     /// to add new static storage fields to your contract.
     #[ink(storage)]
     pub struct SimpleContract {
-        /// Stores a single `AmmPool` value on the storage.
-        pool: AmmPool,
-        /// Supply of tokens
-        reserves: Mapping<TokenId, Balance>,
-        /// Balances for accounts
-        balances: Mapping<(AccountId, TokenId), Balance>,
-        /// Fees accumulated in the contract
-        fees: Mapping<TokenId, Balance>,
+        /// Token pair of each pool
+        pools: Mapping<PoolId, AmmPool>,
+        /// Number of pools created, which is also the id of the next pool
+        pool_count: PoolId,
+        /// Pool of each token pair, keyed by `derive_pool_key`
+        pool_ids: Mapping<[u8; 32], PoolId>,
+        /// Pools trading each token, in creation order
+        token_pools: Mapping<TokenId, Vec<PoolId>>,
+        /// Supply of tokens in each pool
+        reserves: Mapping<(PoolId, TokenId), Balance>,
+        /// Tokens each account has deposited into each pool, net of what its withdrawals paid
+        /// out. Only deposits and withdrawals update it; swaps are settled by transfers alone.
+        balances: Mapping<BalanceKey, Balance>,
+        /// Protocol share of the swap fees accumulated in each pool, for the owner to withdraw
+        fees: Mapping<(PoolId, TokenId), Balance>,
+        /// Account allowed to perform administrative actions
+        owner: AccountId,
+        /// Number of swaps performed by each account
+        swaps_by_account: Mapping<AccountId, u64>,
+        /// Total fees ever accrued in each pool, both the protocol and LP shares, unaffected by
+        /// withdrawals
+        fees_accrued: Mapping<(PoolId, TokenId), Balance>,
+        /// Start of the window used for each pool's fee APR estimates
+        fee_checkpoints: Mapping<PoolId, FeeCheckpoint>,
+        /// Account allowed to trigger an emergency pause, unset until the owner appoints one
+        guardian: Option<AccountId>,
+        /// Whether the contract is frozen by the guardian
+        emergency_paused: bool,
+        /// Operator-defined code explaining the current emergency pause, zero when not paused
+        pause_reason: u8,
+        /// Whether swaps and deposits are halted by the owner
+        paused: bool,
+        /// Whether a message that moves tokens is currently executing. Kept in its own storage
+        /// cell, which is written immediately rather than when the message returns, so a
+        /// re-entrant call sees the lock held
+        locked: Lazy<bool>,
+        /// Account credited when protocol fees are collected
+        protocol_fee_recipient: AccountId,
+        /// Block timestamp at which each pool was created
+        created_at: Mapping<PoolId, Timestamp>,
+        /// Registered decimals of each token
+        decimals: Mapping<TokenId, u8>,
+        /// Price history of each pool, indexed in recording order
+        price_checkpoints: Mapping<(PoolId, u32), PriceCheckpoint>,
+        /// Number of recorded price checkpoints of each pool
+        price_checkpoint_count: Mapping<PoolId, u32>,
+        /// Pending swap commitment of each account and the block it was made in
+        swap_commitments: Mapping<AccountId, (Hash, BlockNumber)>,
+        /// Maximum swaps per account in a single block, zero meaning unlimited
+        swap_rate_limit: u32,
+        /// Block of each account's latest swap and the swaps it made in that block, tracked
+        /// only while a rate limit is set
+        swaps_in_block: Mapping<AccountId, (BlockNumber, u32)>,
+        /// LP tokens of each pool held by each account
+        lp_balances: Mapping<(AccountId, PoolId), Balance>,
+        /// Total supply of LP tokens of each pool
+        total_lp_supply: Mapping<PoolId, Balance>,
+        /// Running sum of each pool's token_0 price (as returned by `get_price`) per block
+        price_0_cumulative: Mapping<PoolId, u128>,
+        /// Running sum of each pool's token_1 price per block
+        price_1_cumulative: Mapping<PoolId, u128>,
+        /// Block up to which each pool's cumulative prices were accumulated, zero before the
+        /// first observation
+        last_update_block: Mapping<PoolId, BlockNumber>,
+        /// LP tokens locked from the first deposit into each pool
+        minimum_liquidity: Balance,
+        /// Reserves plus unpaid protocol and LP fees of each token summed over all pools, which the
+        /// contract's own token balance must cover
+        token_liabilities: Mapping<TokenId, Balance>,
+        /// Sum of the LP balances of all accounts in each pool
+        lp_balance_sum: Mapping<PoolId, Balance>,
+        /// Share of each swap fee kept for the protocol, in basis points of the fee. The rest is
+        /// earned by the pool's LPs
+        protocol_fee_bps: u16,
+        /// LP share of the swap fees of each pool, held until its LPs are paid. The share earned
+        /// by the locked minimum liquidity is never paid out
+        lp_fees: Mapping<(PoolId, TokenId), Balance>,
+        /// LP fees earned per LP token of each pool since its creation, scaled by `PRECISION`.
+        /// The sum wraps on overflow, so differences must be taken with wrapping subtraction
+        fee_growth: Mapping<(PoolId, TokenId), Balance>,
+        /// Fee growth of each pool token up to which each account's LP fees were settled
+        fee_growth_paid: Mapping<BalanceKey, Balance>,
+        /// LP fees settled to each account but not yet paid out
+        unclaimed_fees: Mapping<BalanceKey, Balance>,
     }
 
     impl SimpleContract {
+        /// Instantiates the contract with a first pool, which gets id 0. Fails with
+        /// `IdenticalTokens` if `token_0 == token_1`.
         #[ink(constructor)]
-        pub fn new(token_0: TokenId, token_1: TokenId) -> Self {
-            Self {
-                pool: AmmPool { token_0, token_1 },
-                reserves: Mapping::default(),
-                balances: Mapping::default(),
-                fees: Mapping::default(),
-            }
+        pub fn new(token_0: TokenId, token_1: TokenId, fee_bps: u16) -> Result<Self, Error> {
+            Self::new_with_minimum_liquidity(token_0, token_1, fee_bps, MINIMUM_LIQUIDITY)
         }
 
+        /// Like `new`, but locks `minimum_liquidity` LP tokens from the first deposit into each
+        /// pool instead of `MINIMUM_LIQUIDITY`.
         #[ink(constructor)]
-        pub fn default() -> Self {
-            Self::new(0, 1)
+        pub fn new_with_minimum_liquidity(
+            token_0: TokenId,
+            token_1: TokenId,
+            fee_bps: u16,
+            minimum_liquidity: Balance,
+        ) -> Result<Self, Error> {
+            let mut contract = Self::with_storage(minimum_liquidity);
+            contract.create_pool(token_0, token_1, fee_bps)?;
+
+            Ok(contract)
+        }
+
+        #[ink(constructor)]
+        pub fn default() -> Result<Self, Error> {
+            Self::new(AccountId::from([0; 32]), AccountId::from([1; 32]), FEE_BPS)
         }
 
+        /// Creates a pool for `token_0` and `token_1` charging `fee_bps` on swaps, and returns
+        /// its id. The tokens are stored in ascending order, so the pool's token_0 is the smaller
+        /// id whichever order they are passed in. Fails if the tokens are identical, if the fee
+        /// is not below 10000 bps, or if a pool for the pair already exists in either order, in
+        /// which case the error carries the existing pool's id.
         #[ink(message)]
-        /// Adds liquidity to the pool. Amount is equal for each token.
-        pub fn add_liquidity(&mut self, amount: Balance) {
-            let (token_0, token_1) = (self.pool.token_0, self.pool.token_1);
+        pub fn create_pool(
+            &mut self,
+            token_0: TokenId,
+            token_1: TokenId,
+            fee_bps: u16,
+        ) -> Result<PoolId, Error> {
+            if token_0 == token_1 {
+                return Err(Error::IdenticalTokens);
+            }
+            if fee_bps >= 10_000 {
+                return Err(Error::InvalidFee);
+            }
+            if let Some(pool_id) = self.get_pool_id(token_0, token_1) {
+                return Err(Error::PoolExists { pool_id });
+            }
+            let (token_0, token_1) = (token_0.min(token_1), token_0.max(token_1));
 
-            // Update pool reserves
-            let old_token_0_amount = self.reserves.get(token_0).unwrap_or_default();
-            let new_token_0_amount = old_token_0_amount + amount;
-            self.reserves.insert(token_0, &new_token_0_amount);
-            let old_token_1_amount = self.reserves.get(token_1).unwrap_or_default();
-            let new_token_1_amount = old_token_1_amount + amount;
-            self.reserves.insert(token_1, &new_token_1_amount);
+            let pool_id = self.pool_count;
+            self.pool_count = pool_id.checked_add(1).ok_or(Error::Overflow)?;
+            self.pools.insert(
+                pool_id,
+                &AmmPool {
+                    token_0,
+                    token_1,
+                    fee_bps,
+                },
+            );
+            self.pool_ids
+                .insert(Self::derive_pool_key(token_0, token_1), &pool_id);
+            for token in [token_0, token_1] {
+                let mut pool_ids = self.pools_for_token(token);
+                pool_ids.push(pool_id);
+                self.token_pools.insert(token, &pool_ids);
+            }
+            self.created_at
+                .insert(pool_id, &self.env().block_timestamp());
+            self.fee_checkpoints.insert(
+                pool_id,
+                &FeeCheckpoint {
+                    block: self.env().block_number(),
+                    ..Default::default()
+                },
+            );
 
-            // Update account's balances
+            Self::env().emit_event(PoolCreated {
+                pool_id,
+                tokens: (token_0, token_1),
+            });
+
+            Ok(pool_id)
+        }
+
+        /// Returns the id of the pool for `token_a` and `token_b`, in either order.
+        #[ink(message)]
+        pub fn get_pool_id(&self, token_a: TokenId, token_b: TokenId) -> Option<PoolId> {
+            self.pool_ids.get(Self::derive_pool_key(token_a, token_b))
+        }
+
+        /// Returns the ids of the pools trading `token`, in creation order, for discovering
+        /// routes through it.
+        #[ink(message)]
+        pub fn pools_for_token(&self, token: TokenId) -> Vec<PoolId> {
+            self.token_pools.get(token).unwrap_or_default()
+        }
+
+        /// Returns the registry key of the pair of `token_a` and `token_b`: the Blake2x256 hash
+        /// of the SCALE-encoded tokens in ascending order, so either argument order gives the
+        /// same key and clients can compute it off-chain.
+        pub fn derive_pool_key(token_a: TokenId, token_b: TokenId) -> [u8; 32] {
+            let mut key = [0u8; 32];
+            ink::env::hash_encoded::<Blake2x256, _>(
+                &(token_a.min(token_b), token_a.max(token_b)),
+                &mut key,
+            );
+            key
+        }
+
+        /// Returns the swap fee of the pool in basis points.
+        #[ink(message)]
+        pub fn get_fee_bps(&self, pool_id: PoolId) -> u16 {
+            self.expect_pool(pool_id).fee_bps
+        }
+
+        /// Returns the tokens, reserves and LP supply of the pool in a single consistent read.
+        #[ink(message)]
+        pub fn get_pool(&self, pool_id: PoolId) -> Result<PoolInfo, Error> {
+            let pool = self.pool(pool_id)?;
+            Ok(PoolInfo {
+                token_0: pool.token_0,
+                token_1: pool.token_1,
+                reserve_0: self.get_reserve(pool_id, pool.token_0),
+                reserve_1: self.get_reserve(pool_id, pool.token_1),
+                total_lp_supply: self.total_supply(pool_id),
+            })
+        }
+
+        /// Returns the number of pools created.
+        #[ink(message)]
+        pub fn get_pool_count(&self) -> PoolId {
+            self.pool_count
+        }
+
+        #[ink(message)]
+        /// Adds liquidity to the pool. The amounts are adjusted to the current reserve ratio,
+        /// using the limiting token; the excess of the other token is not taken. Returns the LP
+        /// tokens minted. Fails if the block number is past `deadline`.
+        pub fn add_liquidity(
+            &mut self,
+            pool_id: PoolId,
+            amount_0: Balance,
+            amount_1: Balance,
+            deadline: BlockNumber,
+        ) -> Result<Balance, Error> {
+            self.ensure_not_expired(deadline)?;
+            let (amount_0, amount_1) = self.optimal_deposit(pool_id, amount_0, amount_1)?;
             let account = self.env().caller();
-            let old_token_0_balance = self.balances.get((account, token_0)).unwrap_or_default();
-            let new_token_0_balance = old_token_0_balance + amount;
-            self.balances
-                .insert((account, token_0), &new_token_0_balance);
-            let old_token_1_balance = self.balances.get((account, token_1)).unwrap_or_default();
-            let new_token_1_balance = old_token_1_balance + amount;
-            self.balances
-                .insert((account, token_1), &new_token_1_balance);
+            self.non_reentrant(|contract| contract.deposit(pool_id, account, amount_0, amount_1))
+        }
 
-            Self::env().emit_event(LiquidityAdded {
-                tokens: (token_0, token_1),
-                amounts: (amount, amount),
-                account,
+        /// Adds liquidity following the Uniswap router signature. The desired amounts are
+        /// adjusted to the current reserve ratio, and the deposit and LP tokens are credited to
+        /// `to`. Returns the LP tokens minted. Fails if the block number is past `deadline` or if
+        /// the adjusted amounts fall below `amount_0_min` or `amount_1_min`.
+        #[ink(message)]
+        #[allow(clippy::too_many_arguments)]
+        pub fn add_liquidity_full(
+            &mut self,
+            pool_id: PoolId,
+            amount_0_desired: Balance,
+            amount_1_desired: Balance,
+            amount_0_min: Balance,
+            amount_1_min: Balance,
+            to: AccountId,
+            deadline: BlockNumber,
+        ) -> Result<Balance, Error> {
+            self.ensure_not_expired(deadline)?;
+
+            let (amount_0, amount_1) =
+                self.optimal_deposit(pool_id, amount_0_desired, amount_1_desired)?;
+            if amount_0 < amount_0_min || amount_1 < amount_1_min {
+                return Err(Error::InsufficientAmount);
+            }
+
+            self.non_reentrant(|contract| contract.deposit(pool_id, to, amount_0, amount_1))
+        }
+
+        /// Checks whether `amount_0` and `amount_1` match the current reserve ratio, returning
+        /// the amount of token_1 that would be deposited. On mismatch, the error carries the
+        /// token_1 amount matching `amount_0`. Any ratio is accepted by an empty pool.
+        #[ink(message)]
+        pub fn check_add_liquidity(
+            &self,
+            pool_id: PoolId,
+            amount_0: Balance,
+            amount_1: Balance,
+        ) -> Result<Balance, Error> {
+            let pool = self.pool(pool_id)?;
+            let reserve_0 = self.get_reserve(pool_id, pool.token_0);
+            let reserve_1 = self.get_reserve(pool_id, pool.token_1);
+            if reserve_0 == 0 || reserve_1 == 0 {
+                return Ok(amount_1);
+            }
+
+            let optimal_amount_1 = Self::proportional_amount(amount_0, reserve_0, reserve_1)?;
+            if amount_1 != optimal_amount_1 {
+                return Err(Error::RatioMismatch { optimal_amount_1 });
+            }
+
+            Ok(amount_1)
+        }
+
+        /// Returns the amount of the other pool token to deposit alongside `amount` of `token`
+        /// for a balanced `add_liquidity` at the current reserve ratio, or 0 for an empty pool.
+        /// Panics if `token` is not in the pool or the computation overflows.
+        #[ink(message)]
+        pub fn optimal_counter_amount(
+            &self,
+            pool_id: PoolId,
+            token: TokenId,
+            amount: Balance,
+        ) -> Balance {
+            let other = self.expect_counterpart(pool_id, token);
+            Self::proportional_amount(
+                amount,
+                self.get_reserve(pool_id, token),
+                self.get_reserve(pool_id, other),
+            )
+            .expect("Counter amount overflowed")
+        }
+
+        /// Swaps `amount` of `token_in` for the other pool token, failing if the output would
+        /// be below `min_amount_out` or if the block number is past `deadline`.
+        #[ink(message)]
+        pub fn swap(
+            &mut self,
+            pool_id: PoolId,
+            token_in: TokenId,
+            amount: Balance,
+            min_amount_out: Balance,
+            deadline: BlockNumber,
+        ) -> Result<Balance, Error> {
+            self.ensure_not_expired(deadline)?;
+            self.non_reentrant(|contract| {
+                contract.execute_swap(pool_id, token_in, amount, min_amount_out)
             })
         }
 
+        /// Swaps the other pool token for exactly `amount_out` of `token_out`, charging the
+        /// input needed including the pool's fee, and returns the input charged. Fails if that
+        /// input exceeds `max_amount_in`. Rounding is in favour of the pool, so the output may
+        /// exceed `amount_out` by a few units.
         #[ink(message)]
-        pub fn swap(&mut self, token_in: TokenId, amount: Balance) -> Balance {
-            // Check that the token is part of the pool
-            assert!(
-                token_in == self.pool.token_0 || token_in == self.pool.token_1,
-                "Token {} does not belong to liquidity pool",
-                token_in
-            );
+        pub fn swap_for_exact_out(
+            &mut self,
+            pool_id: PoolId,
+            token_out: TokenId,
+            amount_out: Balance,
+            max_amount_in: Balance,
+        ) -> Result<Balance, Error> {
+            let token_in = self.counterpart(pool_id, token_out)?;
+            let reserve_in = self.get_reserve(pool_id, token_in);
+            let reserve_out = self.get_reserve(pool_id, token_out);
+            if amount_out >= reserve_out {
+                return Err(Error::InsufficientReserve);
+            }
 
-            // Set proper tokens and reserves for pool
-            let (token_in, token_out) = if token_in == self.pool.token_0 {
-                (self.pool.token_0, self.pool.token_1)
-            } else {
-                (self.pool.token_1, self.pool.token_0)
-            };
-            let reserve_in = self.reserves.get(token_in).unwrap_or_default();
-            let reserve_out = self.reserves.get(token_out).unwrap_or_default();
+            let amount_in = Self::calculate_amount_in(
+                amount_out,
+                reserve_in,
+                reserve_out,
+                self.pool(pool_id)?.fee_bps,
+            )?;
+            if amount_in > max_amount_in {
+                return Err(Error::ExcessiveInput);
+            }
+
+            self.non_reentrant(|contract| {
+                contract.execute_swap(pool_id, token_in, amount_in, amount_out)
+            })?;
+            Ok(amount_in)
+        }
 
-            // Subtract 0.3% fee.
-            let token_in_amount = amount * 997 / 1000;
+        /// Swaps `amount` of `token_in`, failing if the execution price (input per unit of
+        /// output, scaled by `PRECISION`) would exceed `max_price`.
+        #[ink(message)]
+        pub fn swap_with_price_limit(
+            &mut self,
+            pool_id: PoolId,
+            token_in: TokenId,
+            amount: Balance,
+            max_price: Balance,
+        ) -> Result<Balance, Error> {
+            // The swap yields exactly the quoted output, so the limit is checked before anything
+            // is written. A zero output is left for the swap to reject.
+            let token_out_amount = self.get_amount_out(pool_id, token_in, amount)?;
+            if token_out_amount > 0 {
+                // A price too large to represent exceeds any limit short of `Balance::MAX`.
+                let price =
+                    Self::mul_div(amount, PRECISION, token_out_amount).unwrap_or(Balance::MAX);
+                if price > max_price {
+                    return Err(Error::PriceLimitExceeded);
+                }
+            }
 
-            // Update fees in storage.
-            let fee = amount - token_in_amount;
-            let old_fee = self.fees.get(token_in).unwrap_or_default();
-            let new_fee = old_fee + fee;
-            self.fees.insert(token_in, &new_fee);
+            self.non_reentrant(|contract| {
+                contract.execute_swap(pool_id, token_in, amount, token_out_amount)
+            })
+        }
 
-            // Calculate amount to send of token out (including 0.3% fee).
+        /// Swaps `amount` of `token_in` in `chunks` sequential parts, failing if the total
+        /// output is below `min_out`. Each part counts as a separate swap, so every part must
+        /// carry at least one unit of input.
+        ///
+        /// Against a single pool this yields the same total as one swap, up to rounding: the
+        /// fee is proportional to the input and the constant product is path-independent.
+        /// Splitting only reduces slippage when the parts are routed across different venues.
+        #[ink(message)]
+        pub fn swap_split(
+            &mut self,
+            pool_id: PoolId,
+            token_in: TokenId,
+            amount: Balance,
+            chunks: u8,
+            min_out: Balance,
+        ) -> Result<Balance, Error> {
+            if chunks == 0 || chunks as Balance > amount {
+                return Err(Error::InvalidChunks);
+            }
 
-            let token_out_amount = if (reserve_in + token_in_amount) != 0 {
-                (reserve_out * token_in_amount) / (reserve_in + token_in_amount)
-            } else {
-                0
-            };
+            let chunk = amount / chunks as Balance;
+            let remainder = amount - chunk * chunks as Balance;
+            let mut token_out_amount = 0;
+            for i in 0..chunks {
+                let part = if i == chunks - 1 {
+                    chunk + remainder
+                } else {
+                    chunk
+                };
+                let amount_out = self
+                    .non_reentrant(|contract| contract.execute_swap(pool_id, token_in, part, 0))?;
+                token_out_amount = amount_out
+                    .checked_add(token_out_amount)
+                    .ok_or(Error::Overflow)?;
+            }
 
-            let pool_reserve_out = self.reserves.get(token_out).unwrap_or_default();
-            assert!(
-                token_out_amount <= pool_reserve_out,
-                "Pool does not have enough balance of token ({})",
-                token_out
+            if token_out_amount < min_out {
+                return Err(Error::SlippageExceeded);
+            }
+            Ok(token_out_amount)
+        }
+
+        /// Commits to a swap without revealing its parameters. `commitment` is the Blake2x256
+        /// hash of the SCALE-encoded `(pool_id, token_in, amount, min_out, nonce)` tuple, with
+        /// `nonce` a `u64`. Replaces any pending commitment of the caller.
+        #[ink(message)]
+        pub fn commit_swap(&mut self, commitment: Hash) {
+            self.swap_commitments.insert(
+                self.env().caller(),
+                &(commitment, self.env().block_number()),
             );
+        }
 
-            // Transfer amount of token_in to contract address.
-            let new_reserve_in = reserve_in + token_in_amount;
-            self.reserves.insert(token_in, &new_reserve_in);
-            let old_balance_in = self
-                .balances
-                .get((self.env().caller(), token_in))
-                .unwrap_or_default();
-            let new_balance_in = old_balance_in + token_in_amount;
-            self.balances
-                .insert((self.env().caller(), token_in), &new_balance_in);
-
-            // Transfer amount_out of token_out to account.
-            let new_reserve_out = reserve_out - token_out_amount;
-            self.reserves.insert(token_out, &new_reserve_out);
-            let old_balance_out = self
-                .balances
-                .get((self.env().caller(), token_out))
-                .unwrap_or_default();
-            let new_balance_out = old_balance_out - token_out_amount;
-            self.balances
-                .insert((self.env().caller(), token_out), &new_balance_out);
+        /// Executes the caller's committed swap, failing if the parameters do not match the
+        /// commitment, if fewer than `COMMIT_REVEAL_DELAY` blocks have passed since committing,
+        /// or if the output is below `min_out`.
+        #[ink(message)]
+        pub fn reveal_swap(
+            &mut self,
+            pool_id: PoolId,
+            token_in: TokenId,
+            amount: Balance,
+            min_out: Balance,
+            nonce: u64,
+        ) -> Result<Balance, Error> {
+            let caller = self.env().caller();
+            let (commitment, committed_at) = self
+                .swap_commitments
+                .get(caller)
+                .ok_or(Error::NoCommitment)?;
 
-            Self::env().emit_event(Swapped {
-                token_in,
-                token_in_amount,
-                token_out,
-                token_out_amount,
-                account: self.env().caller(),
-            });
+            let revealed = self
+                .env()
+                .hash_encoded::<Blake2x256, _>(&(pool_id, token_in, amount, min_out, nonce));
+            if Hash::from(revealed) != commitment {
+                return Err(Error::CommitmentMismatch);
+            }
+            if self.env().block_number() < committed_at + COMMIT_REVEAL_DELAY {
+                return Err(Error::RevealTooEarly);
+            }
+            self.swap_commitments.remove(caller);
 
-            token_out_amount
+            self.non_reentrant(|contract| contract.execute_swap(pool_id, token_in, amount, min_out))
         }
 
+        /// Lends `amount` of `token_out` to `callback` and then calls its
+        /// `FlashSwapCallback::on_flash_swap(initiator, token_out, amount, data)` message. By the
+        /// time the callback returns it must have transferred enough of either pool token back to
+        /// the contract that the product of the reserves does not decrease once the pool's fee
+        /// is taken from each repayment; otherwise the whole call reverts with
+        /// `FlashRepaymentInsufficient`.
         #[ink(message)]
-        pub fn remove_liquidity(&mut self) {
-            // todo!()
+        pub fn flash_swap(
+            &mut self,
+            pool_id: PoolId,
+            token_out: TokenId,
+            amount: Balance,
+            callback: AccountId,
+            data: Vec<u8>,
+        ) -> Result<(), Error> {
+            self.non_reentrant(|contract| {
+                contract.execute_flash_swap(pool_id, token_out, amount, callback, data)
+            })
         }
 
-        /// Returns the current value of the pool's reserves.
+        /// Swaps `amount_in` of `path[0]` for the last token of `path`, hopping through the pool
+        /// of each consecutive pair and feeding each output into the next hop. Only the first
+        /// and last tokens are transferred. Fails with `NoRoute` if the path has fewer than two
+        /// tokens or a pair has no pool, and with `SlippageExceeded` if the final output is
+        /// below `min_amount_out`.
+        ///
+        /// `min_outs` is either empty or holds one minimum output per hop, failing the route
+        /// with `HopSlippageExceeded` at the first hop that falls short, e.g. when an
+        /// intermediate pool was manipulated.
         #[ink(message)]
-        pub fn get_reserve(&self, token: TokenId) -> Balance {
-            self.reserves.get(token).unwrap_or_default()
+        pub fn swap_route(
+            &mut self,
+            path: Vec<TokenId>,
+            amount_in: Balance,
+            min_amount_out: Balance,
+            min_outs: Vec<Balance>,
+        ) -> Result<Balance, Error> {
+            if path.len() < 2 {
+                return Err(Error::NoRoute);
+            }
+            if !min_outs.is_empty() && min_outs.len() != path.len() - 1 {
+                return Err(Error::InvalidMinOuts);
+            }
+            let pool_ids = path
+                .windows(2)
+                .map(|pair| self.get_pool_id(pair[0], pair[1]).ok_or(Error::NoRoute))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            self.non_reentrant(|contract| {
+                let mut amount = amount_in;
+                for (hop, (pool_id, token_in)) in pool_ids.iter().zip(&path).enumerate() {
+                    amount = contract.apply_swap(*pool_id, *token_in, amount, 0)?;
+                    if min_outs.get(hop).is_some_and(|min_out| amount < *min_out) {
+                        return Err(Error::HopSlippageExceeded { hop: hop as u32 });
+                    }
+                }
+                if amount < min_amount_out {
+                    return Err(Error::SlippageExceeded);
+                }
+
+                // The whole route counts as one swap towards the caller's limit.
+                let account = contract.env().caller();
+                contract.record_swap(account)?;
+                contract.transfer_in(path[0], account, amount_in)?;
+                contract.transfer_out(path[path.len() - 1], account, amount)?;
+
+                Ok(amount)
+            })
         }
 
-        /// Returns the current value of account's balances for a given token.
+        /// Returns the amount of the other pool token that swapping `amount` of `token_in` would
+        /// yield. Panics if `token_in` is not in the pool or the computation overflows.
         #[ink(message)]
-        pub fn get_balance(&self, token: TokenId) -> Balance {
-            self.balances
-                .get((self.env().caller(), token))
-                .unwrap_or_default()
+        pub fn quote_swap(&self, pool_id: PoolId, token_in: TokenId, amount: Balance) -> Balance {
+            self.get_amount_out(pool_id, token_in, amount)
+                .expect("Swap quote failed")
         }
 
-        /// Returns the total accumulated fees.
+        /// Returns the output `swap` would give for `amount_in` of `token_in`, using the same fee
+        /// and constant-product math without touching storage. Returns 0 for an empty pool.
         #[ink(message)]
-        pub fn get_fees(&self, token: TokenId) -> Balance {
-            self.fees.get(token).unwrap_or_default()
+        pub fn get_amount_out(
+            &self,
+            pool_id: PoolId,
+            token_in: TokenId,
+            amount_in: Balance,
+        ) -> Result<Balance, Error> {
+            let token_out = self.counterpart(pool_id, token_in)?;
+            let reserve_in = self.get_reserve(pool_id, token_in);
+            let reserve_out = self.get_reserve(pool_id, token_out);
+            if reserve_in == 0 || reserve_out == 0 {
+                return Ok(0);
+            }
+
+            let token_in_amount = Self::amount_after_fee(amount_in, self.pool(pool_id)?.fee_bps)?;
+            Self::calculate_amount_out(token_in_amount, reserve_in, reserve_out)
         }
-    }
 
-    // /// Unit tests in Rust are normally defined within such a `#[cfg(test)]`
-    // /// module and test functions are marked with a `#[test]` attribute.
-    // /// The below code is technically just normal Rust code.
-    // #[cfg(test)]
-    // mod tests {
-    //     /// Imports all the definitions from the outer scope so we can use them here.
-    //     use super::*;
-    //
-    //     /// We test if the default constructor does its job.
-    //     #[ink::test]
-    //     fn default_works() {
-    //         let simple_contract = SimpleContract::default();
-    //         assert_eq!(simple_contract.get(), false);
-    //     }
-    //
-    //     /// We test a simple use case of our contract.
-    //     #[ink::test]
-    //     fn it_works() {
-    //         let mut simple_contract = SimpleContract::new(false);
-    //         assert_eq!(simple_contract.get(), false);
-    //         simple_contract.flip();
-    //         assert_eq!(simple_contract.get(), true);
-    //     }
-    // }
-    //
+        /// Returns the input needed at each hop of `path` for the route to yield `amount_out` of
+        /// its last token, computed backward from the output with the exact-output formula of
+        /// `swap_for_exact_out`. The first amount is the input to swap along the path and the last
+        /// is `amount_out`. Fails with `NoRoute` as `swap_route` does, and with
+        /// `InsufficientReserve` if a pool cannot supply the output required of it.
+        #[ink(message)]
+        pub fn get_amounts_in(
+            &self,
+            path: Vec<TokenId>,
+            amount_out: Balance,
+        ) -> Result<Vec<Balance>, Error> {
+            if path.len() < 2 {
+                return Err(Error::NoRoute);
+            }
+
+            let mut amounts = Vec::with_capacity(path.len());
+            let mut amount = amount_out;
+            amounts.push(amount);
+            for pair in path.windows(2).rev() {
+                let pool_id = self.get_pool_id(pair[0], pair[1]).ok_or(Error::NoRoute)?;
+                let reserve_in = self.get_reserve(pool_id, pair[0]);
+                let reserve_out = self.get_reserve(pool_id, pair[1]);
+                if amount >= reserve_out {
+                    return Err(Error::InsufficientReserve);
+                }
+                amount = Self::calculate_amount_in(
+                    amount,
+                    reserve_in,
+                    reserve_out,
+                    self.pool(pool_id)?.fee_bps,
+                )?;
+                amounts.push(amount);
+            }
+            amounts.reverse();
+
+            Ok(amounts)
+        }
+
+        /// Returns the output of swapping `amount` of token_0 for token_1 and of token_1 for
+        /// token_0, respectively.
+        #[ink(message)]
+        pub fn get_two_sided_quote(&self, pool_id: PoolId, amount: Balance) -> (Balance, Balance) {
+            let pool = self.expect_pool(pool_id);
+            (
+                self.quote_swap(pool_id, pool.token_0, amount),
+                self.quote_swap(pool_id, pool.token_1, amount),
+            )
+        }
+
+        /// Returns the amount of `token_in` to swap so that the pool's spot price of `token_in`
+        /// (units of the other token per unit of `token_in`, scaled by `PRECISION`) moves down to
+        /// `external_price`, including the swap fee. Returns zero if the price is already at or
+        /// below the target, or if the pool is empty.
+        #[ink(message)]
+        pub fn optimal_arb_amount(
+            &self,
+            pool_id: PoolId,
+            token_in: TokenId,
+            external_price: Balance,
+        ) -> Balance {
+            let token_out = self.expect_counterpart(pool_id, token_in);
+            let reserve_in = self.get_reserve(pool_id, token_in);
+            let reserve_out = self.get_reserve(pool_id, token_out);
+            if external_price == 0 || Self::spot_price(reserve_in, reserve_out) <= external_price {
+                return 0;
+            }
+
+            // With k = x * y held constant and a target price p = y' / x', the new input
+            // reserve is x' = sqrt(k / p).
+            let scaled_out = Self::mul_div(reserve_out, PRECISION, external_price)
+                .expect("Arbitrage amount overflowed");
+            let target_reserve_in = Self::isqrt_product(reserve_in, scaled_out);
+            let net_amount = target_reserve_in.saturating_sub(reserve_in);
+
+            // Gross up so that the amount left after the fee reaches the target.
+            let fee_factor = 10_000 - self.get_fee_bps(pool_id) as Balance;
+            Self::mul_div_ceil(net_amount, 10_000, fee_factor).expect("Arbitrage amount overflowed")
+        }
+
+        /// Returns, for each of `price_levels`, the cumulative amount of `token_in` needed to
+        /// move its spot price down to that level (see `optimal_arb_amount`). Levels at or
+        /// above the current price need no input.
+        #[ink(message)]
+        pub fn get_liquidity_depth(
+            &self,
+            pool_id: PoolId,
+            token_in: TokenId,
+            price_levels: Vec<Balance>,
+        ) -> Vec<Balance> {
+            price_levels
+                .into_iter()
+                .map(|price| self.optimal_arb_amount(pool_id, token_in, price))
+                .collect()
+        }
+
+        /// Returns the minimum output for swapping `amount` of `token_in` given a slippage
+        /// tolerance in basis points, suitable as a swap's minimum received amount.
+        #[ink(message)]
+        pub fn get_minimum_received(
+            &self,
+            pool_id: PoolId,
+            token_in: TokenId,
+            amount: Balance,
+            slippage_bps: u16,
+        ) -> Balance {
+            let tolerance = 10_000u128.saturating_sub(slippage_bps as u128);
+            Self::mul_div(
+                self.quote_swap(pool_id, token_in, amount),
+                tolerance,
+                10_000,
+            )
+            .expect("Minimum received overflowed")
+        }
+
+        /// Returns `(expected_out, min_out)` for swapping `amount` of `token_in` with a slippage
+        /// tolerance in basis points.
+        #[ink(message)]
+        pub fn prepare_swap(
+            &self,
+            pool_id: PoolId,
+            token_in: TokenId,
+            amount: Balance,
+            slippage_bps: u16,
+        ) -> (Balance, Balance) {
+            (
+                self.quote_swap(pool_id, token_in, amount),
+                self.get_minimum_received(pool_id, token_in, amount, slippage_bps),
+            )
+        }
+
+        /// Burns `liquidity` of the caller's LP tokens and withdraws the proportional share of
+        /// both reserves, together with all the LP fees the caller's position has earned, and
+        /// returns the amounts paid out. Amounts round down in favour of the pool. The minimum
+        /// liquidity locked by the first deposit can never be burned, so the pool always keeps
+        /// part of its reserves.
+        #[ink(message)]
+        pub fn remove_liquidity(
+            &mut self,
+            pool_id: PoolId,
+            liquidity: Balance,
+        ) -> Result<(Balance, Balance), Error> {
+            self.remove_liquidity_to(pool_id, liquidity, self.env().caller())
+        }
+
+        /// Like `remove_liquidity`, but pays the withdrawn amounts out to `to`. The LP tokens
+        /// are still burned from the caller's position.
+        #[ink(message)]
+        pub fn remove_liquidity_to(
+            &mut self,
+            pool_id: PoolId,
+            liquidity: Balance,
+            to: AccountId,
+        ) -> Result<(Balance, Balance), Error> {
+            self.non_reentrant(|contract| {
+                let (amount_0, amount_1) = contract.withdraw(pool_id, liquidity, to)?;
+
+                let pool = contract.pool(pool_id)?;
+                contract.transfer_out(pool.token_0, to, amount_0)?;
+                contract.transfer_out(pool.token_1, to, amount_1)?;
+
+                Ok((amount_0, amount_1))
+            })
+        }
+
+        /// Burns `liquidity` LP tokens and exits entirely into `out_token`, swapping the other side
+        /// of the withdrawal through the pool. Fails if the total received is below `min_out`.
+        #[ink(message)]
+        pub fn remove_liquidity_single(
+            &mut self,
+            pool_id: PoolId,
+            liquidity: Balance,
+            out_token: TokenId,
+            min_out: Balance,
+        ) -> Result<Balance, Error> {
+            self.non_reentrant(|contract| {
+                contract.withdraw_single(pool_id, liquidity, out_token, min_out)
+            })
+        }
+
+        /// Returns the caller's LP token balance.
+        #[ink(message)]
+        pub fn get_lp_balance(&self, pool_id: PoolId) -> Balance {
+            self.lp_balance_of(self.env().caller(), pool_id)
+        }
+
+        /// Returns the total supply of LP tokens.
+        #[ink(message)]
+        pub fn total_supply(&self, pool_id: PoolId) -> Balance {
+            self.total_lp_supply.get(pool_id).unwrap_or_default()
+        }
+
+        /// Returns `account`'s LP tokens, share of the supply, token balances and swap count in
+        /// one read.
+        #[ink(message)]
+        pub fn account_summary(&self, pool_id: PoolId, account: AccountId) -> AccountSummary {
+            let pool = self.expect_pool(pool_id);
+            let lp_balance = self.lp_balance_of(account, pool_id);
+            let total_supply = self.total_supply(pool_id);
+            let share_bps = if total_supply == 0 {
+                0
+            } else {
+                Self::mul_div(lp_balance, 10_000, total_supply).unwrap_or_default() as u32
+            };
+
+            AccountSummary {
+                lp_balance,
+                share_bps,
+                balance_0: self.balance_of(account, pool_id, pool.token_0),
+                balance_1: self.balance_of(account, pool_id, pool.token_1),
+                swap_count: self.get_account_swap_count(account),
+            }
+        }
+
+        /// Returns the number of swaps performed by `account`.
+        #[ink(message)]
+        pub fn get_account_swap_count(&self, account: AccountId) -> u64 {
+            self.swaps_by_account.get(account).unwrap_or_default()
+        }
+
+        /// Moves the start of the fee APR window to the current block. Can only be called by the
+        /// owner, since anyone else could shrink the window to inflate the estimate.
+        #[ink(message)]
+        pub fn checkpoint_fees(&mut self, pool_id: PoolId) -> Result<(), Error> {
+            self.check_owner()?;
+
+            let pool = self.pool(pool_id)?;
+            self.fee_checkpoints.insert(
+                pool_id,
+                &FeeCheckpoint {
+                    block: self.env().block_number(),
+                    fees_0: self
+                        .fees_accrued
+                        .get((pool_id, pool.token_0))
+                        .unwrap_or_default(),
+                    fees_1: self
+                        .fees_accrued
+                        .get((pool_id, pool.token_1))
+                        .unwrap_or_default(),
+                },
+            );
+            Ok(())
+        }
+
+        /// Returns an estimate of the annualized fee yield of the pool's LPs in basis points.
+        ///
+        /// Fees accrued since the last checkpoint are valued in token_0 at the current spot
+        /// price, reduced to the LPs' share at the current `protocol_fee_bps`, and annualized
+        /// linearly over `BLOCKS_PER_YEAR` against a pool value of twice the token_0 reserve.
+        /// This assumes the recent fee rate and protocol share persist, ignores compounding and
+        /// price moves within the window, and returns zero for an empty window or pool.
+        #[ink(message)]
+        pub fn get_fee_apr_bps(&self, pool_id: PoolId) -> u32 {
+            let pool = self.expect_pool(pool_id);
+            let checkpoint = self.fee_checkpoints.get(pool_id).unwrap_or_default();
+            let reserve_0 = self.get_reserve(pool_id, pool.token_0);
+            let reserve_1 = self.get_reserve(pool_id, pool.token_1);
+            let elapsed = self.env().block_number().saturating_sub(checkpoint.block) as Balance;
+            if reserve_0 == 0 || reserve_1 == 0 || elapsed == 0 {
+                return 0;
+            }
+
+            let fees_0 = self
+                .fees_accrued
+                .get((pool_id, pool.token_0))
+                .unwrap_or_default()
+                - checkpoint.fees_0;
+            let fees_1 = self
+                .fees_accrued
+                .get((pool_id, pool.token_1))
+                .unwrap_or_default()
+                - checkpoint.fees_1;
+            let fee_value = fees_0.saturating_add(
+                Self::proportional_amount(fees_1, reserve_1, reserve_0).unwrap_or_default(),
+            );
+
+            // Scaling by the LP share in basis points yields the APR in basis points. Dividing by
+            // `elapsed` and then by the pool value floors the same as dividing by their product,
+            // which could overflow.
+            let lp_share_bps = 10_000 - self.protocol_fee_bps as Balance;
+            let apr = Self::mul_div(fee_value, lp_share_bps * BLOCKS_PER_YEAR, elapsed)
+                .map(|per_year| per_year / reserve_0 / 2)
+                .unwrap_or(Balance::MAX);
+            apr.min(u32::MAX as Balance) as u32
+        }
+
+        /// Zeroes the accumulated fees for `token` without paying them out, for correcting the
+        /// fee accounting. Reserves are left untouched, and the tokens backing the zeroed fees
+        /// stay in the contract as surplus that `skim` can recover. Can only be called by the
+        /// owner, and fails for tokens outside the pool.
+        #[ink(message)]
+        pub fn reset_fees(&mut self, pool_id: PoolId, token: TokenId) -> Result<(), Error> {
+            self.check_owner()?;
+            self.counterpart(pool_id, token)?;
+
+            let previous = self.get_fees(pool_id, token);
+            self.set_fee(pool_id, token, 0);
+
+            Self::env().emit_event(FeesReset {
+                pool_id,
+                token,
+                previous,
+            });
+            Ok(())
+        }
+
+        /// Transfers the accumulated fees of `token` to `to`, zeroing the accumulator, and returns
+        /// the amount withdrawn. Can only be called by the owner.
+        #[ink(message)]
+        pub fn withdraw_fees(
+            &mut self,
+            pool_id: PoolId,
+            token: TokenId,
+            to: AccountId,
+        ) -> Result<Balance, Error> {
+            self.check_owner()?;
+            self.counterpart(pool_id, token)?;
+
+            self.non_reentrant(|contract| contract.sweep_fees(pool_id, token, to))
+        }
+
+        /// Transfers the accumulated fees of both pool tokens to the protocol fee recipient,
+        /// zeroing both accumulators. Returns the amounts withdrawn for token_0 and token_1.
+        /// Can only be called by the owner.
+        #[ink(message)]
+        pub fn withdraw_all_fees(&mut self, pool_id: PoolId) -> Result<(Balance, Balance), Error> {
+            self.check_owner()?;
+
+            let pool = self.pool(pool_id)?;
+            let recipient = self.protocol_fee_recipient;
+            self.non_reentrant(|contract| {
+                Ok((
+                    contract.sweep_fees(pool_id, pool.token_0, recipient)?,
+                    contract.sweep_fees(pool_id, pool.token_1, recipient)?,
+                ))
+            })
+        }
+
+        /// Transfers the contract's holdings of `token` beyond what the pools owe, their reserves
+        /// and uncollected fees, to `to` and returns the amount. This recovers fees zeroed by
+        /// `reset_fees` and tokens sent to the contract directly. Can only be called by the owner.
+        #[ink(message)]
+        pub fn skim(&mut self, token: TokenId, to: AccountId) -> Result<Balance, Error> {
+            self.check_owner()?;
+            self.ensure_not_emergency_paused()?;
+
+            let surplus = self
+                .token_balance(token)?
+                .saturating_sub(self.liabilities_of(token));
+            self.non_reentrant(|contract| {
+                Self::env().emit_event(Skimmed {
+                    token,
+                    amount: surplus,
+                    recipient: to,
+                });
+                contract.transfer_out(token, to, surplus)?;
+
+                Ok(surplus)
+            })
+        }
+
+        /// Returns the contract's balance of `token` minus what the pools owe, their reserves and
+        /// unpaid fees, saturating at the bounds of `i128`. A positive value is surplus left by
+        /// donations or `reset_fees` that `skim` can recover, a negative value accounting drift.
+        #[ink(message)]
+        pub fn get_discrepancy(&self, token: TokenId) -> i128 {
+            let balance = self
+                .token_balance(token)
+                .expect("Token balance query failed");
+            let to_signed = |amount: Balance| i128::try_from(amount).unwrap_or(i128::MAX);
+            to_signed(balance).saturating_sub(to_signed(self.liabilities_of(token)))
+        }
+
+        /// Transfers native tokens accidentally sent to the contract to `to`. Pool tokens are
+        /// tracked separately, so only the free native balance above the existential deposit
+        /// can be rescued. Can only be called by the owner.
+        #[ink(message)]
+        pub fn rescue_native(&mut self, to: AccountId, amount: Balance) -> Result<(), Error> {
+            self.check_owner()?;
+            self.ensure_not_emergency_paused()?;
+
+            let free_balance = self
+                .env()
+                .balance()
+                .saturating_sub(self.env().minimum_balance());
+            if amount > free_balance {
+                return Err(Error::InsufficientNativeBalance { free_balance });
+            }
+
+            self.env()
+                .transfer(to, amount)
+                .map_err(|_| Error::TransferFailed)
+        }
+
+        /// Sets the account credited when protocol fees are collected. Can only be called by the
+        /// owner.
+        #[ink(message)]
+        pub fn set_protocol_fee_recipient(&mut self, recipient: AccountId) -> Result<(), Error> {
+            self.check_owner()?;
+            self.protocol_fee_recipient = recipient;
+            Ok(())
+        }
+
+        /// Returns the account credited when protocol fees are collected.
+        #[ink(message)]
+        pub fn get_protocol_fee_recipient(&self) -> AccountId {
+            self.protocol_fee_recipient
+        }
+
+        /// Sets the share of each swap fee kept for the protocol, in basis points of the fee. The
+        /// rest is earned by the pool's LPs. Fails with `InvalidFee` above 10000. Can only be
+        /// called by the owner.
+        #[ink(message)]
+        pub fn set_protocol_fee_bps(&mut self, protocol_fee_bps: u16) -> Result<(), Error> {
+            self.check_owner()?;
+            if protocol_fee_bps > 10_000 {
+                return Err(Error::InvalidFee);
+            }
+            self.protocol_fee_bps = protocol_fee_bps;
+            Ok(())
+        }
+
+        /// Returns the share of each swap fee kept for the protocol, in basis points of the fee.
+        #[ink(message)]
+        pub fn get_protocol_fee_bps(&self) -> u16 {
+            self.protocol_fee_bps
+        }
+
+        /// Sets the maximum number of swaps an account may perform per block, zero meaning
+        /// unlimited. Can only be called by the owner.
+        #[ink(message)]
+        pub fn set_swap_rate_limit(&mut self, limit: u32) -> Result<(), Error> {
+            self.check_owner()?;
+            self.swap_rate_limit = limit;
+            Ok(())
+        }
+
+        /// Sets the account allowed to trigger an emergency pause, which must differ from the
+        /// owner so that one compromised key cannot both act and block the response. Until it is
+        /// set, no emergency pause can be triggered. Can only be called by the owner.
+        #[ink(message)]
+        pub fn set_guardian(&mut self, guardian: AccountId) -> Result<(), Error> {
+            self.check_owner()?;
+            if guardian == self.owner {
+                return Err(Error::GuardianIsOwner);
+            }
+            self.guardian = Some(guardian);
+            Ok(())
+        }
+
+        /// Halts or resumes swaps and deposits, e.g. while a pricing bug is investigated.
+        /// Withdrawals keep working so liquidity providers can always exit. Can only be called
+        /// by the owner.
+        #[ink(message)]
+        pub fn set_paused(&mut self, paused: bool) -> Result<(), Error> {
+            self.check_owner()?;
+            self.paused = paused;
+            Ok(())
+        }
+
+        /// Returns whether swaps and deposits are halted by the owner.
+        #[ink(message)]
+        pub fn is_paused(&self) -> bool {
+            self.paused
+        }
+
+        /// Freezes all liquidity and swap operations, including withdrawals, fee collection and
+        /// rescues, for exploit containment, recording `reason` for incident communication. Can
+        /// only be called by the guardian.
+        #[ink(message)]
+        pub fn emergency_pause(&mut self, reason: u8) -> Result<(), Error> {
+            self.check_guardian()?;
+            self.emergency_paused = true;
+            self.pause_reason = reason;
+
+            Self::env().emit_event(Paused { reason });
+            Ok(())
+        }
+
+        /// Lifts an emergency pause and clears its reason. Can only be called by the guardian.
+        #[ink(message)]
+        pub fn emergency_unpause(&mut self) -> Result<(), Error> {
+            self.check_guardian()?;
+            self.emergency_paused = false;
+            self.pause_reason = 0;
+            Ok(())
+        }
+
+        /// Returns the reason code of the current emergency pause, or zero if not paused.
+        #[ink(message)]
+        pub fn get_pause_reason(&self) -> u8 {
+            self.pause_reason
+        }
+
+        /// Returns whether the contract is frozen by the guardian.
+        #[ink(message)]
+        pub fn is_emergency_paused(&self) -> bool {
+            self.emergency_paused
+        }
+
+        /// Runs the internal consistency checks of the pool, for audits and monitoring.
+        ///
+        /// Mappings cannot be iterated on-chain, so the sums are checked against running totals
+        /// maintained on every write. A token balance that cannot be read counts as unbacked.
+        #[ink(message)]
+        pub fn invariant_report(&self, pool_id: PoolId) -> InvariantReport {
+            let pool = self.expect_pool(pool_id);
+            let reserve_0 = self.get_reserve(pool_id, pool.token_0);
+            let reserve_1 = self.get_reserve(pool_id, pool.token_1);
+
+            // Minting starts at sqrt(k) and withdrawals round in favour of the pool, while
+            // swaps only grow k, so the supply can never exceed sqrt(k).
+            let total_supply = self.total_supply(pool_id);
+            let k_consistent =
+                Self::wide_mul(total_supply, total_supply) <= Self::wide_mul(reserve_0, reserve_1);
+
+            let shares_consistent =
+                self.lp_balance_sum.get(pool_id).unwrap_or_default() == total_supply;
+
+            let backed = |token: TokenId| {
+                self.token_balance(token)
+                    .is_ok_and(|balance| balance >= self.liabilities_of(token))
+            };
+            let reserves_backed = backed(pool.token_0) && backed(pool.token_1);
+
+            let checkpoint = self.fee_checkpoints.get(pool_id).unwrap_or_default();
+            let fees_within_accrued = |token: TokenId, checkpoint_fees: Balance| {
+                let accrued = self.fees_accrued.get((pool_id, token)).unwrap_or_default();
+                self.unpaid_fees(pool_id, token) <= accrued && checkpoint_fees <= accrued
+            };
+            let no_negative_implied = fees_within_accrued(pool.token_0, checkpoint.fees_0)
+                && fees_within_accrued(pool.token_1, checkpoint.fees_1);
+
+            InvariantReport {
+                k_consistent,
+                shares_consistent,
+                reserves_backed,
+                no_negative_implied,
+            }
+        }
+
+        /// Returns the block timestamp at which the pool was created.
+        #[ink(message)]
+        pub fn get_created_at(&self, pool_id: PoolId) -> Option<Timestamp> {
+            self.created_at.get(pool_id)
+        }
+
+        /// Returns all tunable parameters of the contract and of `pool_id` in one read.
+        #[ink(message)]
+        pub fn get_config(&self, pool_id: PoolId) -> Config {
+            Config {
+                swap_rate_limit: self.swap_rate_limit,
+                paused: self.paused,
+                emergency_paused: self.emergency_paused,
+                owner: self.owner,
+                guardian: self.guardian,
+                protocol_fee_recipient: self.protocol_fee_recipient,
+                protocol_fee_bps: self.protocol_fee_bps,
+                min_initial_liquidity: self.minimum_liquidity,
+                fee_bps: self.expect_pool(pool_id).fee_bps,
+            }
+        }
+
+        /// Registers the number of decimals of `token`. Can only be called by the owner.
+        #[ink(message)]
+        pub fn set_decimals(&mut self, token: TokenId, decimals: u8) -> Result<(), Error> {
+            self.check_owner()?;
+            if decimals > MAX_DECIMALS {
+                return Err(Error::InvalidDecimals { token });
+            }
+            self.decimals.insert(token, &decimals);
+            Ok(())
+        }
+
+        /// Registers the decimals of several tokens at once. The whole batch is rejected if any
+        /// entry has implausible decimals. Can only be called by the owner.
+        #[ink(message)]
+        pub fn register_tokens_batch(&mut self, entries: Vec<(TokenId, u8)>) -> Result<(), Error> {
+            self.check_owner()?;
+            if let Some(&(token, _)) = entries
+                .iter()
+                .find(|(_, decimals)| *decimals > MAX_DECIMALS)
+            {
+                return Err(Error::InvalidDecimals { token });
+            }
+
+            for (token, decimals) in entries {
+                self.decimals.insert(token, &decimals);
+            }
+            Ok(())
+        }
+
+        /// Returns the registered decimals of `token`, or zero if none were registered.
+        #[ink(message)]
+        pub fn get_decimals(&self, token: TokenId) -> u8 {
+            self.decimals.get(token).unwrap_or_default()
+        }
+
+        /// Returns the current value of the pool's reserves.
+        #[ink(message)]
+        pub fn get_reserve(&self, pool_id: PoolId, token: TokenId) -> Balance {
+            self.reserves.get((pool_id, token)).unwrap_or_default()
+        }
+
+        /// Returns the spot price of `token` in units of the other pool token, scaled by
+        /// `PRECISION`.
+        #[ink(message)]
+        pub fn get_price(&self, pool_id: PoolId, token: TokenId) -> Balance {
+            let other = self.expect_counterpart(pool_id, token);
+            Self::spot_price(
+                self.get_reserve(pool_id, token),
+                self.get_reserve(pool_id, other),
+            )
+        }
+
+        /// Returns the fee-inclusive marginal cost of buying token_0 (in token_1) and of buying
+        /// token_1 (in token_0), scaled by `PRECISION`. Both exceed the mid price by the fee, so
+        /// their product exceeds `PRECISION * PRECISION` by the resulting spread. Prices too large
+        /// to represent saturate at `Balance::MAX`.
+        #[ink(message)]
+        pub fn get_marginal_prices(&self, pool_id: PoolId) -> (Balance, Balance) {
+            let pool = self.expect_pool(pool_id);
+            let reserve_0 = self.get_reserve(pool_id, pool.token_0);
+            let reserve_1 = self.get_reserve(pool_id, pool.token_1);
+            let fee_factor = 10_000 - pool.fee_bps as Balance;
+            let with_fee =
+                |price: Balance| Self::mul_div(price, 10_000, fee_factor).unwrap_or(Balance::MAX);
+
+            (
+                with_fee(Self::spot_price(reserve_0, reserve_1)),
+                with_fee(Self::spot_price(reserve_1, reserve_0)),
+            )
+        }
+
+        /// Returns the reserve of the other pool token implied by `token` having
+        /// `known_reserve` and a spot price of `price` (as returned by `get_price`).
+        /// The result is rounded to the nearest unit.
+        #[ink(message)]
+        pub fn implied_reserve(
+            &self,
+            pool_id: PoolId,
+            token: TokenId,
+            known_reserve: Balance,
+            price: Balance,
+        ) -> Balance {
+            self.expect_counterpart(pool_id, token);
+
+            // Multiply at full width before dividing so that only the final division rounds.
+            let (quotient, remainder) =
+                Self::div_wide(Self::wide_mul(known_reserve, price), PRECISION)
+                    .expect("Implied reserve overflowed");
+            if remainder >= PRECISION / 2 {
+                quotient.checked_add(1).expect("Implied reserve overflowed")
+            } else {
+                quotient
+            }
+        }
+
+        /// Returns the spot price of `token_in` in units of `token_out` from the raw reserve
+        /// ratio, scaled by `PRECISION`.
+        #[ink(message)]
+        pub fn get_spot_price_raw(
+            &self,
+            pool_id: PoolId,
+            token_in: TokenId,
+            token_out: TokenId,
+        ) -> Balance {
+            let other = self.expect_counterpart(pool_id, token_in);
+            assert!(
+                token_out == other,
+                "Token {:?} is not the counterpart of token {:?} in the pool",
+                token_out,
+                token_in
+            );
+
+            self.get_price(pool_id, token_in)
+        }
+
+        /// Returns the spot price of one whole `token_in` in whole units of `token_out`, scaled
+        /// by `PRECISION`, adjusting the raw reserve ratio for the tokens' registered decimals.
+        /// Panics if the adjusted price does not fit in a `Balance`.
+        #[ink(message)]
+        pub fn get_spot_price_adjusted(
+            &self,
+            pool_id: PoolId,
+            token_in: TokenId,
+            token_out: TokenId,
+        ) -> Balance {
+            let raw_price = self.get_spot_price_raw(pool_id, token_in, token_out);
+            let decimals_in = self.get_decimals(token_in);
+            let decimals_out = self.get_decimals(token_out);
+
+            if decimals_in >= decimals_out {
+                raw_price
+                    .checked_mul(10u128.pow((decimals_in - decimals_out) as u32))
+                    .expect("Adjusted price overflowed")
+            } else {
+                raw_price / 10u128.pow((decimals_out - decimals_in) as u32)
+            }
+        }
+
+        /// Returns the price of token_0 in token_1 (as returned by `get_price`) recorded at or
+        /// most recently before `block`.
+        #[ink(message)]
+        pub fn get_price_at(&self, pool_id: PoolId, block: BlockNumber) -> Result<Balance, Error> {
+            let count = self.checkpoints_until(pool_id, block);
+            if count == 0 {
+                return Err(Error::NoCheckpoint);
+            }
+            self.price_checkpoints
+                .get((pool_id, count - 1))
+                .map(|checkpoint| checkpoint.price)
+                .ok_or(Error::NoCheckpoint)
+        }
+
+        /// Returns `points` time-weighted average prices of token_0 in token_1 (as returned by
+        /// `get_price`), splitting the last `window_secs` seconds into equal sub-windows, oldest
+        /// first. Fails if no price was recorded before the window starts, or if the window is
+        /// shorter than one block per point.
+        #[ink(message)]
+        pub fn get_twap_series(
+            &self,
+            pool_id: PoolId,
+            window_secs: u32,
+            points: u8,
+        ) -> Result<Vec<Balance>, Error> {
+            let sub_window = window_secs / SECONDS_PER_BLOCK / BlockNumber::from(points.max(1));
+            if points == 0 || sub_window == 0 {
+                return Err(Error::InsufficientHistory);
+            }
+
+            let now = self.env().block_number();
+            let start = now
+                .checked_sub(sub_window * BlockNumber::from(points))
+                .ok_or(Error::InsufficientHistory)?;
+            if self.checkpoints_until(pool_id, start) == 0 {
+                return Err(Error::InsufficientHistory);
+            }
+
+            (0..BlockNumber::from(points))
+                .map(|point| {
+                    let from = start + point * sub_window;
+                    self.average_price(pool_id, from, from + sub_window)
+                })
+                .collect()
+        }
+
+        /// Returns the cumulative token_0 and token_1 prices of the pool, brought up to the
+        /// current block, and that block. The time-weighted average price between two
+        /// observations is the difference of the cumulatives divided by the blocks elapsed;
+        /// the sums wrap on overflow, so differences must be taken with wrapping subtraction.
+        #[ink(message)]
+        pub fn get_price_cumulative(&self, pool_id: PoolId) -> (u128, u128, BlockNumber) {
+            self.current_price_cumulative(pool_id)
+        }
+
+        /// Returns the impermanent loss of `account`'s position in basis points (negative for a
+        /// loss), given `entry_price`, the price of token_0 in token_1 (as returned by
+        /// `get_price`) when the position was opened.
+        ///
+        /// With `r = current_price / entry_price`, a constant product position is worth
+        /// `2 * sqrt(r) / (1 + r)` times the value of holding the deposited tokens, so the
+        /// result is `(2 * sqrt(r) / (1 + r) - 1) * 10000`. Returns zero if the account has no
+        /// position or the entry price is zero.
+        #[ink(message)]
+        pub fn estimate_il_bps(
+            &self,
+            pool_id: PoolId,
+            account: AccountId,
+            entry_price: Balance,
+        ) -> i32 {
+            let has_position = self.lp_balance_of(account, pool_id) > 0;
+            if !has_position || entry_price == 0 {
+                return 0;
+            }
+
+            let pool = self.expect_pool(pool_id);
+            let price = self.get_price(pool_id, pool.token_0);
+            // A ratio too large to represent leaves the position worth next to nothing.
+            let value_ratio = Self::mul_div(price, PRECISION, entry_price)
+                .and_then(|ratio| {
+                    let sqrt_ratio = Self::isqrt_product(ratio, PRECISION);
+                    let denominator = PRECISION.checked_add(ratio).ok_or(Error::Overflow)?;
+                    Self::mul_div(2 * sqrt_ratio, PRECISION, denominator)
+                })
+                .unwrap_or(0);
+
+            ((value_ratio as i128 - PRECISION as i128) * 10_000 / PRECISION as i128) as i32
+        }
+
+        /// Returns the value of both reserves and the unpaid protocol and LP fees of both tokens,
+        /// denominated in `denom` with the other token converted at the spot price.
+        #[ink(message)]
+        pub fn get_total_value(&self, pool_id: PoolId, denom: TokenId) -> Result<Balance, Error> {
+            let other = self.counterpart(pool_id, denom)?;
+            let reserve_denom = self.get_reserve(pool_id, denom);
+            let reserve_other = self.get_reserve(pool_id, other);
+            let backing_other = reserve_other
+                .checked_add(self.unpaid_fees(pool_id, other))
+                .ok_or(Error::Overflow)?;
+            let other_value =
+                Self::proportional_amount(backing_other, reserve_other, reserve_denom)?;
+
+            reserve_denom
+                .checked_add(self.unpaid_fees(pool_id, denom))
+                .and_then(|backing_denom| backing_denom.checked_add(other_value))
+                .ok_or(Error::Overflow)
+        }
+
+        /// Returns the reserves of token_0 and token_1 plus the LP fees held for them, i.e. the
+        /// full backing of LP positions. Fails with `Overflow` if a sum exceeds `Balance`.
+        #[ink(message)]
+        pub fn get_effective_reserves(&self, pool_id: PoolId) -> Result<(Balance, Balance), Error> {
+            let pool = self.pool(pool_id)?;
+            let effective_reserve = |token| {
+                self.get_reserve(pool_id, token)
+                    .checked_add(self.get_lp_fees(pool_id, token))
+                    .ok_or(Error::Overflow)
+            };
+            Ok((
+                effective_reserve(pool.token_0)?,
+                effective_reserve(pool.token_1)?,
+            ))
+        }
+
+        /// Returns token_0's share of the pool reserves in basis points, or zero for an empty pool.
+        #[ink(message)]
+        pub fn get_reserve_ratio_bps(&self, pool_id: PoolId) -> u32 {
+            let pool = self.expect_pool(pool_id);
+            let reserve_0 = self.get_reserve(pool_id, pool.token_0);
+            let reserve_1 = self.get_reserve(pool_id, pool.token_1);
+
+            let total = reserve_0.saturating_add(reserve_1);
+            if total == 0 {
+                return 0;
+            }
+            Self::mul_div(reserve_0, 10_000, total).unwrap_or_default() as u32
+        }
+
+        /// Returns the share of `token`'s holdings made up of unpaid protocol and LP fees, in basis
+        /// points, or zero if the pool holds none of it.
+        #[ink(message)]
+        pub fn get_fee_ratio_bps(&self, pool_id: PoolId, token: TokenId) -> u32 {
+            let fees = self.unpaid_fees(pool_id, token);
+            let reserve = self.get_reserve(pool_id, token);
+
+            let total = reserve.saturating_add(fees);
+            if total == 0 {
+                return 0;
+            }
+            Self::mul_div(fees, 10_000, total).unwrap_or_default() as u32
+        }
+
+        /// Returns the current value of the caller's balances for a given token.
+        ///
+        /// Deprecated: use `balance_of` with the caller's account instead.
+        #[ink(message)]
+        pub fn get_balance(&self, pool_id: PoolId, token: TokenId) -> Balance {
+            self.balance_of(self.env().caller(), pool_id, token)
+        }
+
+        /// Returns the current value of account's balances for a given token.
+        #[ink(message)]
+        pub fn balance_of(&self, account: AccountId, pool_id: PoolId, token: TokenId) -> Balance {
+            self.balances
+                .get((account, pool_id, token))
+                .unwrap_or_default()
+        }
+
+        /// Returns the total accumulated fees.
+        #[ink(message)]
+        pub fn get_fees(&self, pool_id: PoolId, token: TokenId) -> Balance {
+            self.fees.get((pool_id, token)).unwrap_or_default()
+        }
+
+        /// Returns the total accumulated fees, failing for tokens outside the pool.
+        #[ink(message)]
+        pub fn get_fees_checked(&self, pool_id: PoolId, token: TokenId) -> Result<Balance, Error> {
+            self.counterpart(pool_id, token)?;
+            Ok(self.get_fees(pool_id, token))
+        }
+
+        /// Returns the LP fees of `token` the pool holds until its LPs are paid.
+        #[ink(message)]
+        pub fn get_lp_fees(&self, pool_id: PoolId, token: TokenId) -> Balance {
+            self.lp_fees.get((pool_id, token)).unwrap_or_default()
+        }
+
+        /// Returns the LP fees of token_0 and token_1 that `account`'s position has earned and
+        /// that have not been paid out yet.
+        #[ink(message)]
+        pub fn get_unclaimed_fees(
+            &self,
+            pool_id: PoolId,
+            account: AccountId,
+        ) -> (Balance, Balance) {
+            let pool = self.expect_pool(pool_id);
+            let unclaimed = |token| {
+                self.unclaimed_fee_of(account, pool_id, token)
+                    .expect("Unclaimed fees overflowed")
+            };
+            (unclaimed(pool.token_0), unclaimed(pool.token_1))
+        }
+
+        /// Returns what a full exit of `account`'s position would pay out at the current
+        /// reserves: its proportional share of both reserves, rounded down as in
+        /// `remove_liquidity`, plus the LP fees it has earned.
+        #[ink(message)]
+        pub fn preview_remove_all(
+            &self,
+            pool_id: PoolId,
+            account: AccountId,
+        ) -> (Balance, Balance) {
+            let pool = self.expect_pool(pool_id);
+            let lp_balance = self.lp_balance_of(account, pool_id);
+            let total_supply = self.total_supply(pool_id);
+            let (fees_0, fees_1) = self.get_unclaimed_fees(pool_id, account);
+            let paid = |token, fees: Balance| {
+                Self::mul_div(lp_balance, self.get_reserve(pool_id, token), total_supply)
+                    .unwrap_or_default()
+                    .saturating_add(fees)
+            };
+            (paid(pool.token_0, fees_0), paid(pool.token_1, fees_1))
+        }
+
+        /// Formats the pool tokens, reserves, fees and price into a single readable line, for
+        /// command-line tooling and debugging.
+        #[cfg(feature = "std")]
+        pub fn summary(&self, pool_id: PoolId) -> String {
+            let pool = self.expect_pool(pool_id);
+            let (token_0, token_1) = (pool.token_0, pool.token_1);
+            format!(
+                "pool {} ({:?}/{:?}): reserves {}/{}, fees {}/{}, price {}",
+                pool_id,
+                token_0,
+                token_1,
+                self.get_reserve(pool_id, token_0),
+                self.get_reserve(pool_id, token_1),
+                self.get_fees(pool_id, token_0),
+                self.get_fees(pool_id, token_1),
+                self.get_price(pool_id, token_0),
+            )
+        }
+
+        /// Contract state with no pools, owned by the caller. Every mapping and lazy field
+        /// addresses the contract's storage, so a second instance sees the same entries.
+        fn with_storage(minimum_liquidity: Balance) -> Self {
+            Self {
+                pools: Mapping::default(),
+                pool_count: 0,
+                pool_ids: Mapping::default(),
+                token_pools: Mapping::default(),
+                reserves: Mapping::default(),
+                balances: Mapping::default(),
+                fees: Mapping::default(),
+                owner: Self::env().caller(),
+                swaps_by_account: Mapping::default(),
+                fees_accrued: Mapping::default(),
+                fee_checkpoints: Mapping::default(),
+                guardian: None,
+                emergency_paused: false,
+                pause_reason: 0,
+                paused: false,
+                locked: Lazy::new(),
+                protocol_fee_recipient: Self::env().caller(),
+                created_at: Mapping::default(),
+                decimals: Mapping::default(),
+                price_checkpoints: Mapping::default(),
+                price_checkpoint_count: Mapping::default(),
+                swap_commitments: Mapping::default(),
+                swap_rate_limit: 0,
+                swaps_in_block: Mapping::default(),
+                lp_balances: Mapping::default(),
+                total_lp_supply: Mapping::default(),
+                price_0_cumulative: Mapping::default(),
+                price_1_cumulative: Mapping::default(),
+                last_update_block: Mapping::default(),
+                minimum_liquidity,
+                token_liabilities: Mapping::default(),
+                lp_balance_sum: Mapping::default(),
+                protocol_fee_bps: 0,
+                lp_fees: Mapping::default(),
+                fee_growth: Mapping::default(),
+                fee_growth_paid: Mapping::default(),
+                unclaimed_fees: Mapping::default(),
+            }
+        }
+
+        /// Adds `amount_0` and `amount_1` to the pool reserves, crediting them to `account`.
+        fn deposit(
+            &mut self,
+            pool_id: PoolId,
+            account: AccountId,
+            amount_0: Balance,
+            amount_1: Balance,
+        ) -> Result<Balance, Error> {
+            self.ensure_not_emergency_paused()?;
+            self.ensure_not_paused()?;
+
+            let pool = self.pool(pool_id)?;
+            self.update_price_cumulative(pool_id);
+            let (token_0, token_1) = (pool.token_0, pool.token_1);
+            let old_token_0_amount = self.get_reserve(pool_id, token_0);
+            let old_token_1_amount = self.get_reserve(pool_id, token_1);
+            let total_lp_supply = self.total_supply(pool_id);
+
+            // The first deposit sets the price, which must be neither zero nor infinite.
+            let bootstrap = old_token_0_amount == 0 || old_token_1_amount == 0;
+            if bootstrap && (amount_0 == 0 || amount_1 == 0) {
+                return Err(Error::InvalidInitialRatio);
+            }
+
+            // Mint LP tokens: the geometric mean of the amounts for the first deposit, and the
+            // share of the smaller relative contribution afterwards.
+            let minted = if bootstrap || total_lp_supply == 0 {
+                Self::isqrt_product(amount_0, amount_1)
+            } else {
+                core::cmp::min(
+                    Self::proportional_amount(amount_0, old_token_0_amount, total_lp_supply)?,
+                    Self::proportional_amount(amount_1, old_token_1_amount, total_lp_supply)?,
+                )
+            };
+            // The very first mint locks `minimum_liquidity` of it away from the depositor.
+            let locked = if total_lp_supply == 0 {
+                self.minimum_liquidity
+            } else {
+                0
+            };
+            let liquidity = minted.saturating_sub(locked);
+            if liquidity == 0 {
+                return Err(Error::InsufficientLiquidityMinted);
+            }
+
+            // Compute every new value before writing, so an overflow leaves storage untouched.
+            let new_lp_balance = self
+                .lp_balance_of(account, pool_id)
+                .checked_add(liquidity)
+                .ok_or(Error::Overflow)?;
+            let new_total_lp_supply = total_lp_supply.checked_add(minted).ok_or(Error::Overflow)?;
+            let new_token_0_amount = old_token_0_amount
+                .checked_add(amount_0)
+                .ok_or(Error::Overflow)?;
+            let new_token_1_amount = old_token_1_amount
+                .checked_add(amount_1)
+                .ok_or(Error::Overflow)?;
+            let new_token_0_balance = self
+                .balance_of(account, pool_id, token_0)
+                .checked_add(amount_0)
+                .ok_or(Error::Overflow)?;
+            let new_token_1_balance = self
+                .balance_of(account, pool_id, token_1)
+                .checked_add(amount_1)
+                .ok_or(Error::Overflow)?;
+
+            // New LP tokens must not earn the fees accrued before they were minted.
+            self.settle_fees(account, pool_id)?;
+
+            // Mint LP tokens
+            self.set_lp_balance(account, pool_id, new_lp_balance);
+            if locked > 0 {
+                self.set_lp_balance(AccountId::from([0; 32]), pool_id, locked);
+            }
+            self.total_lp_supply.insert(pool_id, &new_total_lp_supply);
+
+            // Update pool reserves
+            self.set_reserve(pool_id, token_0, new_token_0_amount);
+            self.set_reserve(pool_id, token_1, new_token_1_amount);
+
+            // Update account's balances
+            self.set_balance(account, pool_id, token_0, new_token_0_balance);
+            self.set_balance(account, pool_id, token_1, new_token_1_balance);
+
+            Self::env().emit_event(LiquidityAdded {
+                pool_id,
+                tokens: (token_0, token_1),
+                amounts: (amount_0, amount_1),
+                account,
+            });
+
+            self.record_price_checkpoint(pool_id);
+
+            let payer = self.env().caller();
+            self.transfer_in(token_0, payer, amount_0)?;
+            self.transfer_in(token_1, payer, amount_1)?;
+
+            Ok(liquidity)
+        }
+
+        /// Burns `liquidity` of the caller's LP tokens and removes the proportional share of
+        /// both reserves from the pool, along with the caller's LP fees, recording `to` as the
+        /// recipient. The returned amounts, fees included, are left for the calling message to
+        /// transfer out.
+        fn withdraw(
+            &mut self,
+            pool_id: PoolId,
+            liquidity: Balance,
+            to: AccountId,
+        ) -> Result<(Balance, Balance), Error> {
+            self.ensure_not_emergency_paused()?;
+
+            let pool = self.pool(pool_id)?;
+            self.update_price_cumulative(pool_id);
+            let account = self.env().caller();
+            let lp_balance = self.lp_balance_of(account, pool_id);
+            if lp_balance == 0 {
+                return Err(Error::NoLiquidity);
+            }
+            if liquidity > lp_balance {
+                return Err(Error::InsufficientBalance);
+            }
+
+            let (token_0, token_1) = (pool.token_0, pool.token_1);
+            let total_lp_supply = self.total_supply(pool_id);
+            let reserve_0 = self.get_reserve(pool_id, token_0);
+            let reserve_1 = self.get_reserve(pool_id, token_1);
+            let amount_0 = Self::mul_div(liquidity, reserve_0, total_lp_supply)?;
+            let amount_1 = Self::mul_div(liquidity, reserve_1, total_lp_supply)?;
+            if amount_0 == 0 && amount_1 == 0 {
+                return Err(Error::InsufficientLiquidityBurned);
+            }
+
+            // Pay out the fees earned by the whole position, not just the burned part.
+            self.settle_fees(account, pool_id)?;
+            let fees_0 = self.claim_fees(account, pool_id, token_0);
+            let fees_1 = self.claim_fees(account, pool_id, token_1);
+            let paid_0 = amount_0.checked_add(fees_0).ok_or(Error::Overflow)?;
+            let paid_1 = amount_1.checked_add(fees_1).ok_or(Error::Overflow)?;
+            Self::env().emit_event(FeesClaimed {
+                pool_id,
+                account,
+                amounts: (fees_0, fees_1),
+                recipient: to,
+            });
+
+            // Burn LP tokens
+            self.set_lp_balance(account, pool_id, lp_balance - liquidity);
+            if lp_balance == liquidity {
+                self.fee_growth_paid.remove((account, pool_id, token_0));
+                self.fee_growth_paid.remove((account, pool_id, token_1));
+            }
+            self.total_lp_supply
+                .insert(pool_id, &(total_lp_supply - liquidity));
+
+            // Update pool reserves
+            self.set_reserve(pool_id, token_0, reserve_0 - amount_0);
+            self.set_reserve(pool_id, token_1, reserve_1 - amount_1);
+
+            // Update account's balances
+            let balance_0 = self.balance_of(account, pool_id, token_0);
+            self.set_balance(
+                account,
+                pool_id,
+                token_0,
+                balance_0.saturating_sub(amount_0),
+            );
+            let balance_1 = self.balance_of(account, pool_id, token_1);
+            self.set_balance(
+                account,
+                pool_id,
+                token_1,
+                balance_1.saturating_sub(amount_1),
+            );
+
+            Self::env().emit_event(LiquidityRemoved {
+                pool_id,
+                tokens: (token_0, token_1),
+                amounts: (amount_0, amount_1),
+                account,
+                recipient: to,
+            });
+
+            self.record_price_checkpoint(pool_id);
+
+            Ok((paid_0, paid_1))
+        }
+
+        /// Burns `liquidity` LP tokens and pays the whole withdrawal out in `out_token`, see
+        /// `remove_liquidity_single`.
+        fn withdraw_single(
+            &mut self,
+            pool_id: PoolId,
+            liquidity: Balance,
+            out_token: TokenId,
+            min_out: Balance,
+        ) -> Result<Balance, Error> {
+            // The exit trades against the pool, so it is halted along with swaps.
+            self.ensure_not_paused()?;
+
+            let other_token = self.counterpart(pool_id, out_token)?;
+            let account = self.env().caller();
+
+            let (amount_0, amount_1) = self.withdraw(pool_id, liquidity, account)?;
+            let (direct_amount, other_amount) = if out_token == self.pool(pool_id)?.token_0 {
+                (amount_0, amount_1)
+            } else {
+                (amount_1, amount_0)
+            };
+
+            // Dust of the other token that swaps to nothing is paid out as is.
+            let fee_bps = self.pool(pool_id)?.fee_bps;
+            let quoted_amount = Self::calculate_amount_out(
+                Self::amount_after_fee(other_amount, fee_bps)?,
+                self.get_reserve(pool_id, other_token),
+                self.get_reserve(pool_id, out_token),
+            )?;
+            if quoted_amount == 0 {
+                if direct_amount < min_out {
+                    return Err(Error::SlippageExceeded);
+                }
+                self.transfer_out(out_token, account, direct_amount)?;
+                self.transfer_out(other_token, account, other_amount)?;
+                return Ok(direct_amount);
+            }
+
+            // Otherwise swap the other side against the reserves left after the withdrawal, with
+            // the same checks and accounting as any other swap.
+            let swapped_amount = self.apply_swap(pool_id, other_token, other_amount, 0)?;
+            self.record_swap(account)?;
+            let total = direct_amount
+                .checked_add(swapped_amount)
+                .ok_or(Error::Overflow)?;
+            if total < min_out {
+                return Err(Error::SlippageExceeded);
+            }
+
+            self.transfer_out(out_token, account, total)?;
+
+            Ok(total)
+        }
+
+        /// Swaps `amount` of `token_in` for the other pool token, failing if the output would
+        /// be below `min_amount_out`, and moves the tokens between the caller and the contract.
+        fn execute_swap(
+            &mut self,
+            pool_id: PoolId,
+            token_in: TokenId,
+            amount: Balance,
+            min_amount_out: Balance,
+        ) -> Result<Balance, Error> {
+            let token_out_amount = self.apply_swap(pool_id, token_in, amount, min_amount_out)?;
+            let account = self.env().caller();
+            self.record_swap(account)?;
+
+            // Tokens move only once every write and the invariant check are done, so a token
+            // whose transfer hook reads the pool observes settled reserves.
+            self.transfer_in(token_in, account, amount)?;
+            self.transfer_out(
+                self.counterpart(pool_id, token_in)?,
+                account,
+                token_out_amount,
+            )?;
+
+            Ok(token_out_amount)
+        }
+
+        /// Updates the pool's storage for a swap of `amount` of `token_in`, failing if the
+        /// output would be below `min_amount_out`. The calling message moves the tokens.
+        fn apply_swap(
+            &mut self,
+            pool_id: PoolId,
+            token_in: TokenId,
+            amount: Balance,
+            min_amount_out: Balance,
+        ) -> Result<Balance, Error> {
+            self.ensure_not_emergency_paused()?;
+            self.ensure_not_paused()?;
+
+            // Find the output token and both reserves
+            let token_out = self.counterpart(pool_id, token_in)?;
+            self.update_price_cumulative(pool_id);
+            let reserve_in = self.get_reserve(pool_id, token_in);
+            let reserve_out = self.get_reserve(pool_id, token_out);
+
+            // Subtract the pool's fee, which must leave something to trade.
+            let token_in_amount = Self::amount_after_fee(amount, self.pool(pool_id)?.fee_bps)?;
+            if token_in_amount == 0 {
+                return Err(Error::AmountTooSmall);
+            }
+
+            // Calculate amount to send of token out (net of the fee).
+            let token_out_amount =
+                Self::calculate_amount_out(token_in_amount, reserve_in, reserve_out)?;
+
+            // Dust inputs truncate to no output, which would still charge a fee.
+            if token_out_amount == 0 {
+                return Err(Error::ZeroOutput);
+            }
+
+            // Protect against the price moving before the transaction executes.
+            if token_out_amount < min_amount_out {
+                return Err(Error::SlippageExceeded);
+            }
+
+            // The output can only approach the reserve asymptotically, so draining it entirely
+            // is rejected.
+            if token_out_amount >= reserve_out {
+                return Err(Error::InsufficientReserve);
+            }
+
+            // Compute every new value before writing, so an overflow leaves storage untouched.
+            let account = self.env().caller();
+            let fee = amount.checked_sub(token_in_amount).ok_or(Error::Overflow)?;
+            let new_reserve_in = reserve_in
+                .checked_add(token_in_amount)
+                .ok_or(Error::Overflow)?;
+            let new_reserve_out = reserve_out
+                .checked_sub(token_out_amount)
+                .ok_or(Error::Overflow)?;
+
+            // Fees are kept outside the reserves, so the product of the reserves must not
+            // decrease even without them. This guards against rounding errors in the output
+            // formula.
+            if Self::wide_mul(new_reserve_in, new_reserve_out)
+                < Self::wide_mul(reserve_in, reserve_out)
+            {
+                return Err(Error::InvariantViolation);
+            }
+
+            // Update fees in storage.
+            self.accrue_fee(pool_id, token_in, fee)?;
+
+            // Move the input into the pool and the output out of it. The swapper's tokens are
+            // settled by real transfers, not by the `balances` ledger of deposits.
+            self.set_reserve(pool_id, token_in, new_reserve_in);
+            self.set_reserve(pool_id, token_out, new_reserve_out);
+
+            Self::env().emit_event(Swapped {
+                pool_id,
+                token_in,
+                token_in_amount,
+                token_out,
+                token_out_amount,
+                account,
+                new_spot_price: Self::spot_price(new_reserve_in, new_reserve_out),
+            });
+
+            self.record_price_checkpoint(pool_id);
+
+            Ok(token_out_amount)
+        }
+
+        /// Counts a swap by `account`, failing if it would exceed the per-block rate limit. Called
+        /// once per swap a message performs, however many pools it trades through.
+        fn record_swap(&mut self, account: AccountId) -> Result<(), Error> {
+            // Only one entry per account is kept, and only while a limit is set.
+            if self.swap_rate_limit > 0 {
+                let block = self.env().block_number();
+                let swaps_in_block = match self.swaps_in_block.get(account) {
+                    Some((last_block, count)) if last_block == block => count,
+                    _ => 0,
+                };
+                if swaps_in_block >= self.swap_rate_limit {
+                    return Err(Error::RateLimited);
+                }
+                self.swaps_in_block
+                    .insert(account, &(block, swaps_in_block + 1));
+            }
+
+            let swap_count = self.get_account_swap_count(account);
+            self.swaps_by_account
+                .insert(account, &swap_count.saturating_add(1));
+            Ok(())
+        }
+
+        /// Splits a swap fee of `fee` in `token` between the protocol, per `protocol_fee_bps`,
+        /// and the pool's LPs, who earn their share in proportion to their LP tokens.
+        fn accrue_fee(
+            &mut self,
+            pool_id: PoolId,
+            token: TokenId,
+            fee: Balance,
+        ) -> Result<(), Error> {
+            let protocol_fee = Self::mul_div(fee, self.protocol_fee_bps as Balance, 10_000)?;
+            let lp_fee = fee - protocol_fee;
+            let total_supply = self.total_supply(pool_id);
+            let growth = if total_supply == 0 {
+                0
+            } else {
+                Self::mul_div(lp_fee, PRECISION, total_supply)?
+            };
+
+            // Compute every new value before writing, so an overflow leaves storage untouched.
+            let new_fee = self
+                .get_fees(pool_id, token)
+                .checked_add(protocol_fee)
+                .ok_or(Error::Overflow)?;
+            let new_lp_fee = self
+                .get_lp_fees(pool_id, token)
+                .checked_add(lp_fee)
+                .ok_or(Error::Overflow)?;
+            let new_accrued = self
+                .fees_accrued
+                .get((pool_id, token))
+                .unwrap_or_default()
+                .checked_add(fee)
+                .ok_or(Error::Overflow)?;
+            let new_growth = self.fee_growth_of(pool_id, token).wrapping_add(growth);
+
+            self.set_fee(pool_id, token, new_fee);
+            self.set_lp_fee(pool_id, token, new_lp_fee);
+            self.fees_accrued.insert((pool_id, token), &new_accrued);
+            self.fee_growth.insert((pool_id, token), &new_growth);
+            Ok(())
+        }
+
+        /// Moves the LP fees `account` has earned in the pool since its last settlement into its
+        /// unclaimed fees. Must run before its LP balance changes, so that each LP token earns
+        /// only the fees accrued while it was held.
+        fn settle_fees(&mut self, account: AccountId, pool_id: PoolId) -> Result<(), Error> {
+            let pool = self.pool(pool_id)?;
+            for token in [pool.token_0, pool.token_1] {
+                let unclaimed = self.unclaimed_fee_of(account, pool_id, token)?;
+                self.set_unclaimed_fee(account, pool_id, token, unclaimed);
+                self.fee_growth_paid.insert(
+                    (account, pool_id, token),
+                    &self.fee_growth_of(pool_id, token),
+                );
+            }
+            Ok(())
+        }
+
+        /// Zeroes `account`'s settled fees of `token` and takes them out of the pool's LP fees,
+        /// returning the amount for the caller to pay out.
+        fn claim_fees(&mut self, account: AccountId, pool_id: PoolId, token: TokenId) -> Balance {
+            let amount = self
+                .unclaimed_fees
+                .get((account, pool_id, token))
+                .unwrap_or_default();
+            self.set_unclaimed_fee(account, pool_id, token, 0);
+            // Claims are paid from fees credited to the pool, so they never exceed them.
+            let lp_fees = self.get_lp_fees(pool_id, token).saturating_sub(amount);
+            self.set_lp_fee(pool_id, token, lp_fees);
+            amount
+        }
+
+        /// LP fees of `token` earned by `account`'s position and not yet paid out: those already
+        /// settled plus those accrued on its LP tokens since.
+        fn unclaimed_fee_of(
+            &self,
+            account: AccountId,
+            pool_id: PoolId,
+            token: TokenId,
+        ) -> Result<Balance, Error> {
+            let key = (account, pool_id, token);
+            let growth = self
+                .fee_growth_of(pool_id, token)
+                .wrapping_sub(self.fee_growth_paid.get(key).unwrap_or_default());
+            let accrued = Self::mul_div(self.lp_balance_of(account, pool_id), growth, PRECISION)?;
+            self.unclaimed_fees
+                .get(key)
+                .unwrap_or_default()
+                .checked_add(accrued)
+                .ok_or(Error::Overflow)
+        }
+
+        /// Protocol and LP fees of `token` the pool holds and has not paid out. Both are backed by
+        /// the contract's balance, so the sum cannot overflow.
+        fn unpaid_fees(&self, pool_id: PoolId, token: TokenId) -> Balance {
+            self.get_fees(pool_id, token)
+                .saturating_add(self.get_lp_fees(pool_id, token))
+        }
+
+        /// Returns the LP fees earned per LP token of the pool, see `fee_growth`.
+        fn fee_growth_of(&self, pool_id: PoolId, token: TokenId) -> Balance {
+            self.fee_growth.get((pool_id, token)).unwrap_or_default()
+        }
+
+        /// Sends `amount` of `token_out` to `callback`, invokes it, and settles the pool from
+        /// whatever it paid back, see `flash_swap`.
+        fn execute_flash_swap(
+            &mut self,
+            pool_id: PoolId,
+            token_out: TokenId,
+            amount: Balance,
+            callback: AccountId,
+            data: Vec<u8>,
+        ) -> Result<(), Error> {
+            self.ensure_not_emergency_paused()?;
+            self.ensure_not_paused()?;
+
+            let token_in = self.counterpart(pool_id, token_out)?;
+            self.update_price_cumulative(pool_id);
+            let fee_bps = self.pool(pool_id)?.fee_bps;
+            let reserve_in = self.get_reserve(pool_id, token_in);
+            let reserve_out = self.get_reserve(pool_id, token_out);
+            if amount == 0 {
+                return Err(Error::ZeroOutput);
+            }
+            if amount >= reserve_out {
+                return Err(Error::InsufficientReserve);
+            }
+
+            // Repayments are measured as the change in the contract's own token balances.
+            let held_in = self.token_balance(token_in)?;
+            let held_out = self.token_balance(token_out)?;
+
+            self.transfer_out(token_out, callback, amount)?;
+            #[cfg(test)]
+            let result = tests::mock::on_flash_swap(callback, token_out, amount, data);
+            #[cfg(not(test))]
+            let result = build_call::<DefaultEnvironment>()
+                .call(callback)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!(
+                        "FlashSwapCallback::on_flash_swap"
+                    )))
+                    .push_arg(self.env().caller())
+                    .push_arg(token_out)
+                    .push_arg(amount)
+                    .push_arg(data),
+                )
+                .returns::<()>()
+                .try_invoke();
+            if !matches!(result, Ok(Ok(()))) {
+                return Err(Error::CallbackFailed);
+            }
+
+            let repaid_in = self.token_balance(token_in)?.saturating_sub(held_in);
+            let repaid_out = self
+                .token_balance(token_out)?
+                .saturating_sub(held_out.saturating_sub(amount));
+
+            // Each repayment is charged the swap fee, which is kept outside the reserves.
+            let net_in = Self::amount_after_fee(repaid_in, fee_bps)?;
+            let net_out = Self::amount_after_fee(repaid_out, fee_bps)?;
+            let new_reserve_in = reserve_in.checked_add(net_in).ok_or(Error::Overflow)?;
+            let new_reserve_out = (reserve_out - amount)
+                .checked_add(net_out)
+                .ok_or(Error::Overflow)?;
+            if Self::wide_mul(new_reserve_in, new_reserve_out)
+                < Self::wide_mul(reserve_in, reserve_out)
+            {
+                return Err(Error::FlashRepaymentInsufficient);
+            }
+
+            self.accrue_fee(pool_id, token_in, repaid_in - net_in)?;
+            self.accrue_fee(pool_id, token_out, repaid_out - net_out)?;
+            self.set_reserve(pool_id, token_in, new_reserve_in);
+            self.set_reserve(pool_id, token_out, new_reserve_out);
+
+            self.record_price_checkpoint(pool_id);
+
+            Ok(())
+        }
+
+        /// Records the current price of token_0 in token_1, replacing the latest checkpoint if
+        /// it was recorded in the same block.
+        fn record_price_checkpoint(&mut self, pool_id: PoolId) {
+            let pool = self.expect_pool(pool_id);
+            let checkpoint = PriceCheckpoint {
+                block: self.env().block_number(),
+                price: self.get_price(pool_id, pool.token_0),
+            };
+
+            let count = self.price_checkpoint_count.get(pool_id).unwrap_or_default();
+            let same_block = count > 0
+                && self
+                    .price_checkpoints
+                    .get((pool_id, count - 1))
+                    .is_some_and(|last| last.block == checkpoint.block);
+            if same_block {
+                self.price_checkpoints
+                    .insert((pool_id, count - 1), &checkpoint);
+            } else {
+                self.price_checkpoints.insert((pool_id, count), &checkpoint);
+                self.price_checkpoint_count.insert(pool_id, &(count + 1));
+            }
+        }
+
+        /// Number of the pool's price checkpoints recorded at or before `block`, found by binary
+        /// search.
+        fn checkpoints_until(&self, pool_id: PoolId, block: BlockNumber) -> u32 {
+            let (mut low, mut high) = (
+                0,
+                self.price_checkpoint_count.get(pool_id).unwrap_or_default(),
+            );
+            while low < high {
+                let mid = low + (high - low) / 2;
+                let checkpoint = self.price_checkpoints.get((pool_id, mid));
+                if checkpoint.is_some_and(|checkpoint| checkpoint.block <= block) {
+                    low = mid + 1;
+                } else {
+                    high = mid;
+                }
+            }
+            low
+        }
+
+        /// Average of the recorded token_0 price over blocks `from..to`, each price weighted by
+        /// the number of blocks it was in effect. Requires a checkpoint at or before `from`.
+        fn average_price(
+            &self,
+            pool_id: PoolId,
+            from: BlockNumber,
+            to: BlockNumber,
+        ) -> Result<Balance, Error> {
+            let mut index = self
+                .checkpoints_until(pool_id, from)
+                .checked_sub(1)
+                .ok_or(Error::InsufficientHistory)?;
+            let mut checkpoint = self
+                .price_checkpoints
+                .get((pool_id, index))
+                .ok_or(Error::InsufficientHistory)?;
+
+            let mut weighted_sum: Balance = 0;
+            let mut block = from;
+            while block < to {
+                let next = self.price_checkpoints.get((pool_id, index + 1));
+                let until = match next {
+                    Some(next) if next.block < to => next.block,
+                    _ => to,
+                };
+                weighted_sum = weighted_sum
+                    .checked_add(
+                        checkpoint
+                            .price
+                            .checked_mul(Balance::from(until - block))
+                            .ok_or(Error::Overflow)?,
+                    )
+                    .ok_or(Error::Overflow)?;
+
+                block = until;
+                if let Some(next) = next {
+                    checkpoint = next;
+                    index += 1;
+                }
+            }
+
+            Ok(weighted_sum / Balance::from(to - from))
+        }
+
+        /// Cumulative prices of the pool as of the current block, accumulating the current
+        /// reserve ratio over the blocks since the last update. Nothing is accumulated before
+        /// the first observation.
+        fn current_price_cumulative(&self, pool_id: PoolId) -> (u128, u128, BlockNumber) {
+            let block = self.env().block_number();
+            let price_0_cumulative = self.price_0_cumulative.get(pool_id).unwrap_or_default();
+            let price_1_cumulative = self.price_1_cumulative.get(pool_id).unwrap_or_default();
+            let last_update_block = self.last_update_block.get(pool_id).unwrap_or_default();
+            let Some(pool) = self.pools.get(pool_id) else {
+                return (price_0_cumulative, price_1_cumulative, block);
+            };
+            if last_update_block == 0 {
+                return (price_0_cumulative, price_1_cumulative, block);
+            }
+
+            let elapsed = u128::from(block.saturating_sub(last_update_block));
+            let reserve_0 = self.get_reserve(pool_id, pool.token_0);
+            let reserve_1 = self.get_reserve(pool_id, pool.token_1);
+            (
+                price_0_cumulative
+                    .wrapping_add(Self::spot_price(reserve_0, reserve_1).wrapping_mul(elapsed)),
+                price_1_cumulative
+                    .wrapping_add(Self::spot_price(reserve_1, reserve_0).wrapping_mul(elapsed)),
+                block,
+            )
+        }
+
+        /// Accumulates the pool's prices up to the current block. Called before the reserves
+        /// change, so each ratio is weighted by the blocks it was in effect.
+        fn update_price_cumulative(&mut self, pool_id: PoolId) {
+            let (price_0_cumulative, price_1_cumulative, block) =
+                self.current_price_cumulative(pool_id);
+            self.price_0_cumulative.insert(pool_id, &price_0_cumulative);
+            self.price_1_cumulative.insert(pool_id, &price_1_cumulative);
+            self.last_update_block.insert(pool_id, &block);
+        }
+
+        /// Adjusts desired deposit amounts to the current reserve ratio. An empty pool accepts the
+        /// desired amounts as given.
+        fn optimal_deposit(
+            &self,
+            pool_id: PoolId,
+            amount_0_desired: Balance,
+            amount_1_desired: Balance,
+        ) -> Result<(Balance, Balance), Error> {
+            let pool = self.pool(pool_id)?;
+            let reserve_0 = self.get_reserve(pool_id, pool.token_0);
+            let reserve_1 = self.get_reserve(pool_id, pool.token_1);
+            if reserve_0 == 0 || reserve_1 == 0 {
+                return Ok((amount_0_desired, amount_1_desired));
+            }
+
+            let amount_1_optimal =
+                Self::proportional_amount(amount_0_desired, reserve_0, reserve_1)?;
+            if amount_1_optimal <= amount_1_desired {
+                return Ok((amount_0_desired, amount_1_optimal));
+            }
+
+            let amount_0_optimal =
+                Self::proportional_amount(amount_1_desired, reserve_1, reserve_0)?;
+            Ok((amount_0_optimal, amount_1_desired))
+        }
+
+        /// Transfers the accumulated fees of `token` to `recipient` and returns the amount.
+        fn sweep_fees(
+            &mut self,
+            pool_id: PoolId,
+            token: TokenId,
+            recipient: AccountId,
+        ) -> Result<Balance, Error> {
+            self.ensure_not_emergency_paused()?;
+
+            let amount = self.get_fees(pool_id, token);
+            self.set_fee(pool_id, token, 0);
+
+            Self::env().emit_event(FeesWithdrawn {
+                pool_id,
+                token,
+                amount,
+                recipient,
+            });
+
+            self.transfer_out(token, recipient, amount)?;
+
+            Ok(amount)
+        }
+
+        /// Pulls `amount` of `token` from `from` into the contract with PSP22 `transfer_from`,
+        /// which requires a prior allowance. Returning the error from a message reverts every
+        /// storage write made before the call.
+        fn transfer_in(
+            &self,
+            token: TokenId,
+            from: AccountId,
+            amount: Balance,
+        ) -> Result<(), Error> {
+            if amount == 0 {
+                return Ok(());
+            }
+
+            #[cfg(test)]
+            let result = tests::mock::transfer(token, from, self.env().account_id(), amount);
+            #[cfg(not(test))]
+            let result = build_call::<DefaultEnvironment>()
+                .call(token)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!(
+                        "PSP22::transfer_from"
+                    )))
+                    .push_arg(from)
+                    .push_arg(self.env().account_id())
+                    .push_arg(amount)
+                    .push_arg(Vec::<u8>::new()),
+                )
+                .returns::<Result<(), PSP22Error>>()
+                .try_invoke();
+            match result {
+                Ok(Ok(Ok(()))) => Ok(()),
+                _ => Err(Error::TransferFailed),
+            }
+        }
+
+        /// Returns the contract's own balance of `token` with PSP22 `balance_of`.
+        fn token_balance(&self, token: TokenId) -> Result<Balance, Error> {
+            #[cfg(test)]
+            let result = tests::mock::balance_of(token, self.env().account_id());
+            #[cfg(not(test))]
+            let result = build_call::<DefaultEnvironment>()
+                .call(token)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("PSP22::balance_of")))
+                        .push_arg(self.env().account_id()),
+                )
+                .returns::<Balance>()
+                .try_invoke();
+            match result {
+                Ok(Ok(balance)) => Ok(balance),
+                _ => Err(Error::TransferFailed),
+            }
+        }
+
+        /// Sends `amount` of `token` from the contract to `to` with PSP22 `transfer`.
+        /// Returning the error from a message reverts every storage write made before the call.
+        fn transfer_out(
+            &self,
+            token: TokenId,
+            to: AccountId,
+            amount: Balance,
+        ) -> Result<(), Error> {
+            if amount == 0 {
+                return Ok(());
+            }
+
+            #[cfg(test)]
+            let result = tests::mock::transfer(token, self.env().account_id(), to, amount);
+            #[cfg(not(test))]
+            let result = build_call::<DefaultEnvironment>()
+                .call(token)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("PSP22::transfer")))
+                        .push_arg(to)
+                        .push_arg(amount)
+                        .push_arg(Vec::<u8>::new()),
+                )
+                .returns::<Result<(), PSP22Error>>()
+                .try_invoke();
+            match result {
+                Ok(Ok(Ok(()))) => Ok(()),
+                _ => Err(Error::TransferFailed),
+            }
+        }
+
+        /// Returns `account`'s LP token balance in the pool.
+        fn lp_balance_of(&self, account: AccountId, pool_id: PoolId) -> Balance {
+            self.lp_balances.get((account, pool_id)).unwrap_or_default()
+        }
+
+        /// Returns the reserves plus unpaid protocol and LP fees of `token` summed over all pools.
+        fn liabilities_of(&self, token: TokenId) -> Balance {
+            self.token_liabilities.get(token).unwrap_or_default()
+        }
+
+        /// Moves the running total of what the pools owe in `token` from `previous` to `amount`.
+        /// The total is bounded by the token's supply, so it cannot overflow.
+        fn update_liabilities(&mut self, token: TokenId, previous: Balance, amount: Balance) {
+            let total = self.liabilities_of(token).saturating_sub(previous);
+            self.token_liabilities
+                .insert(token, &total.saturating_add(amount));
+        }
+
+        /// Stores the reserve of `token`.
+        fn set_reserve(&mut self, pool_id: PoolId, token: TokenId, amount: Balance) {
+            self.update_liabilities(token, self.get_reserve(pool_id, token), amount);
+            #[cfg(test)]
+            let existed = self.reserves.contains((pool_id, token));
+            self.reserves.insert((pool_id, token), &amount);
+            #[cfg(test)]
+            tests::footprint::track(|footprint| &mut footprint.reserves, existed, true);
+        }
+
+        /// Stores `account`'s balance of `token`. A zero balance removes the entry to reclaim its
+        /// storage deposit.
+        fn set_balance(
+            &mut self,
+            account: AccountId,
+            pool_id: PoolId,
+            token: TokenId,
+            amount: Balance,
+        ) {
+            let key = (account, pool_id, token);
+            #[cfg(test)]
+            let existed = self.balances.contains(key);
+            if amount == 0 {
+                self.balances.remove(key);
+            } else {
+                self.balances.insert(key, &amount);
+            }
+            #[cfg(test)]
+            tests::footprint::track(|footprint| &mut footprint.balances, existed, amount != 0);
+        }
+
+        /// Stores `account`'s LP token balance, removing zeroed ones.
+        fn set_lp_balance(&mut self, account: AccountId, pool_id: PoolId, amount: Balance) {
+            // Balances never sum past the supply, which is checked on minting.
+            let sum = self
+                .lp_balance_sum
+                .get(pool_id)
+                .unwrap_or_default()
+                .saturating_sub(self.lp_balance_of(account, pool_id))
+                .saturating_add(amount);
+            self.lp_balance_sum.insert(pool_id, &sum);
+
+            #[cfg(test)]
+            let existed = self.lp_balances.contains((account, pool_id));
+            if amount == 0 {
+                self.lp_balances.remove((account, pool_id));
+            } else {
+                self.lp_balances.insert((account, pool_id), &amount);
+            }
+            #[cfg(test)]
+            tests::footprint::track(|footprint| &mut footprint.lp_balances, existed, amount != 0);
+        }
+
+        /// Stores the accumulated fees of `token`.
+        fn set_fee(&mut self, pool_id: PoolId, token: TokenId, amount: Balance) {
+            self.update_liabilities(token, self.get_fees(pool_id, token), amount);
+            #[cfg(test)]
+            let existed = self.fees.contains((pool_id, token));
+            self.fees.insert((pool_id, token), &amount);
+            #[cfg(test)]
+            tests::footprint::track(|footprint| &mut footprint.fees, existed, true);
+        }
+
+        /// Stores the LP fees of `token` held by the pool.
+        fn set_lp_fee(&mut self, pool_id: PoolId, token: TokenId, amount: Balance) {
+            self.update_liabilities(token, self.get_lp_fees(pool_id, token), amount);
+            self.lp_fees.insert((pool_id, token), &amount);
+        }
+
+        /// Stores `account`'s settled but unpaid LP fees of `token`, removing zeroed ones.
+        fn set_unclaimed_fee(
+            &mut self,
+            account: AccountId,
+            pool_id: PoolId,
+            token: TokenId,
+            amount: Balance,
+        ) {
+            if amount == 0 {
+                self.unclaimed_fees.remove((account, pool_id, token));
+            } else {
+                self.unclaimed_fees
+                    .insert((account, pool_id, token), &amount);
+            }
+        }
+
+        /// Fails if the caller is not the owner.
+        fn check_owner(&self) -> Result<(), Error> {
+            if self.env().caller() != self.owner {
+                return Err(Error::NotOwner);
+            }
+            Ok(())
+        }
+
+        /// Fails if the caller is not the guardian.
+        fn check_guardian(&self) -> Result<(), Error> {
+            if self.guardian != Some(self.env().caller()) {
+                return Err(Error::NotGuardian);
+            }
+            Ok(())
+        }
+
+        /// Fails if the contract is frozen by the guardian.
+        fn ensure_not_emergency_paused(&self) -> Result<(), Error> {
+            if self.emergency_paused {
+                return Err(Error::EmergencyPaused);
+            }
+            Ok(())
+        }
+
+        /// Runs `f` while holding the reentrancy lock, failing if it is already held, e.g. by a
+        /// token contract calling back during a transfer. The lock is released whether `f`
+        /// succeeds or fails.
+        fn non_reentrant<T>(
+            &mut self,
+            f: impl FnOnce(&mut Self) -> Result<T, Error>,
+        ) -> Result<T, Error> {
+            if self.locked.get().unwrap_or_default() {
+                return Err(Error::Reentrancy);
+            }
+
+            self.locked.set(&true);
+            let result = f(self);
+            self.locked.set(&false);
+            result
+        }
+
+        /// Fails if swaps and deposits are halted by the owner.
+        fn ensure_not_paused(&self) -> Result<(), Error> {
+            if self.paused {
+                return Err(Error::Paused);
+            }
+            Ok(())
+        }
+
+        /// Fails if the current block number is past `deadline`.
+        fn ensure_not_expired(&self, deadline: BlockNumber) -> Result<(), Error> {
+            if self.env().block_number() > deadline {
+                return Err(Error::Expired);
+            }
+            Ok(())
+        }
+
+        /// Returns the pool with id `pool_id`, failing if it does not exist.
+        fn pool(&self, pool_id: PoolId) -> Result<AmmPool, Error> {
+            self.pools.get(pool_id).ok_or(Error::PoolNotFound)
+        }
+
+        /// Returns the pool with id `pool_id`, panicking if it does not exist. Used by
+        /// read-only messages that return plain values.
+        fn expect_pool(&self, pool_id: PoolId) -> AmmPool {
+            self.pool(pool_id)
+                .unwrap_or_else(|_| panic!("Pool {} does not exist", pool_id))
+        }
+
+        /// Returns the other token of the pool, e.g. the output token of a swap of `token`,
+        /// failing if the pool does not exist or the token does not belong to it.
+        fn counterpart(&self, pool_id: PoolId, token: TokenId) -> Result<TokenId, Error> {
+            let pool = self.pool(pool_id)?;
+            if token == pool.token_0 {
+                Ok(pool.token_1)
+            } else if token == pool.token_1 {
+                Ok(pool.token_0)
+            } else {
+                Err(Error::TokenNotInPool)
+            }
+        }
+
+        /// Returns the other token of the pool, panicking if the token does not belong to it.
+        /// Used by read-only messages that return plain values.
+        fn expect_counterpart(&self, pool_id: PoolId, token: TokenId) -> TokenId {
+            self.expect_pool(pool_id);
+            self.counterpart(pool_id, token)
+                .unwrap_or_else(|_| panic!("Token {:?} does not belong to pool {}", token, pool_id))
+        }
+
+        /// Subtracts a swap fee of `fee_bps` from an input amount.
+        fn amount_after_fee(amount: Balance, fee_bps: u16) -> Result<Balance, Error> {
+            let scaled = amount
+                .checked_mul(10_000 - fee_bps as Balance)
+                .ok_or(Error::Overflow)?;
+            Ok(scaled / 10_000)
+        }
+
+        /// Amount of the other token matching `amount` at the ratio `reserve_in:reserve_out`.
+        fn proportional_amount(
+            amount: Balance,
+            reserve_in: Balance,
+            reserve_out: Balance,
+        ) -> Result<Balance, Error> {
+            if reserve_in == 0 {
+                return Ok(0);
+            }
+            Self::mul_div(amount, reserve_out, reserve_in)
+        }
+
+        /// Price of the input token in units of the output token, scaled by `PRECISION`.
+        /// Saturates at `Balance::MAX` for a price too large to represent.
+        fn spot_price(reserve_in: Balance, reserve_out: Balance) -> Balance {
+            if reserve_in == 0 {
+                return 0;
+            }
+            Self::mul_div(reserve_out, PRECISION, reserve_in).unwrap_or(Balance::MAX)
+        }
+
+        /// Floor of the square root of `n`, using Newton's method. Returns `r` with
+        /// `r * r <= n < (r + 1) * (r + 1)` for every `u128`, including `u128::MAX`: iterates
+        /// never drop below the root, so `x + n / x` stays within range.
+        fn isqrt(n: u128) -> u128 {
+            if n < 2 {
+                return n;
+            }
+
+            // Start from ceil(n / 2), which is above the root and cannot overflow.
+            let mut x = n;
+            let mut y = n / 2 + (n & 1);
+            while y < x {
+                x = y;
+                y = (x + n / x) / 2;
+            }
+            x
+        }
+
+        /// Floor of the square root of `a * b`, computed without overflowing on the product.
+        fn isqrt_product(a: u128, b: u128) -> u128 {
+            if let Some(n) = a.checked_mul(b) {
+                return Self::isqrt(n);
+            }
+
+            // The root lies between the two factors; binary search it on the 256-bit product.
+            let target = Self::wide_mul(a, b);
+            let (mut low, mut high) = (a.min(b), a.max(b));
+            while low < high {
+                let mid = low + (high - low).div_ceil(2);
+                if Self::wide_mul(mid, mid) <= target {
+                    low = mid;
+                } else {
+                    high = mid - 1;
+                }
+            }
+            low
+        }
+
+        /// `a * b / denominator` rounded down, with a 256-bit intermediate product so only a
+        /// quotient that does not fit in a `Balance` overflows. `denominator` must be non-zero.
+        fn mul_div(a: u128, b: u128, denominator: u128) -> Result<Balance, Error> {
+            let (quotient, _) = Self::div_wide(Self::wide_mul(a, b), denominator)?;
+            Ok(quotient)
+        }
+
+        /// `a * b / denominator` rounded up, see `mul_div`.
+        fn mul_div_ceil(a: u128, b: u128, denominator: u128) -> Result<Balance, Error> {
+            let (quotient, remainder) = Self::div_wide(Self::wide_mul(a, b), denominator)?;
+            if remainder == 0 {
+                Ok(quotient)
+            } else {
+                quotient.checked_add(1).ok_or(Error::Overflow)
+            }
+        }
+
+        /// Divides a 256-bit `(high, low)` value by `denominator`, returning the quotient and
+        /// remainder. Fails with `Overflow` if the quotient needs more than 128 bits.
+        fn div_wide((high, low): (u128, u128), denominator: u128) -> Result<(u128, u128), Error> {
+            if high >= denominator {
+                return Err(Error::Overflow);
+            }
+            if high == 0 {
+                return Ok((low / denominator, low % denominator));
+            }
+
+            // Schoolbook long division, one bit of `low` at a time. The remainder stays below
+            // `denominator`; a bit shifted out of it means the true value exceeds `denominator`.
+            let (mut quotient, mut remainder) = (0u128, high);
+            for bit in (0..128).rev() {
+                let carry = remainder >> 127;
+                remainder = (remainder << 1) | ((low >> bit) & 1);
+                quotient <<= 1;
+                if carry == 1 || remainder >= denominator {
+                    remainder = remainder.wrapping_sub(denominator);
+                    quotient |= 1;
+                }
+            }
+            Ok((quotient, remainder))
+        }
+
+        /// Full 256-bit product of `a` and `b` as `(high, low)` halves, which compare in the same
+        /// order as the products themselves.
+        fn wide_mul(a: u128, b: u128) -> (u128, u128) {
+            const MASK: u128 = u64::MAX as u128;
+            let (a_hi, a_lo) = (a >> 64, a & MASK);
+            let (b_hi, b_lo) = (b >> 64, b & MASK);
+
+            let lo_lo = a_lo * b_lo;
+            let hi_lo = a_hi * b_lo;
+            let lo_hi = a_lo * b_hi;
+            let hi_hi = a_hi * b_hi;
+
+            // Sum the middle column in 64-bit limbs so no step can overflow.
+            let middle = (lo_lo >> 64) + (hi_lo & MASK) + (lo_hi & MASK);
+            let low = (middle << 64) | (lo_lo & MASK);
+            let high = hi_hi + (hi_lo >> 64) + (lo_hi >> 64) + (middle >> 64);
+            (high, low)
+        }
+
+        /// Constant product output for `token_in_amount` (already net of fees).
+        fn calculate_amount_out(
+            token_in_amount: Balance,
+            reserve_in: Balance,
+            reserve_out: Balance,
+        ) -> Result<Balance, Error> {
+            let denominator = reserve_in
+                .checked_add(token_in_amount)
+                .ok_or(Error::Overflow)?;
+            if denominator == 0 {
+                return Ok(0);
+            }
+            Self::mul_div(reserve_out, token_in_amount, denominator)
+        }
+
+        /// Gross input, including a fee of `fee_bps`, for which the constant product formula
+        /// yields at least `amount_out`. Both steps round up. Requires `amount_out < reserve_out`.
+        fn calculate_amount_in(
+            amount_out: Balance,
+            reserve_in: Balance,
+            reserve_out: Balance,
+            fee_bps: u16,
+        ) -> Result<Balance, Error> {
+            let token_in_amount =
+                Self::mul_div_ceil(reserve_in, amount_out, reserve_out - amount_out)?;
+            Self::mul_div_ceil(token_in_amount, 10_000, 10_000 - fee_bps as Balance)
+        }
+    }
+
+    /// Unit tests run in the off-chain environment, which cannot call other contracts, so the
+    /// PSP22 tokens and flash swap borrowers the contract calls are replaced by `mock`.
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use ink::env::test::{self, DefaultAccounts};
+        use ink::env::DefaultEnvironment;
+        use std::{cell::RefCell, rc::Rc};
+
+        /// Account the contract runs at, distinct from every default account.
+        const CONTRACT: [u8; 32] = [0xC0; 32];
+
+        const TOKEN_A: [u8; 32] = [0xA0; 32];
+        const TOKEN_B: [u8; 32] = [0xB0; 32];
+
+        const NO_DEADLINE: BlockNumber = BlockNumber::MAX;
+
+        /// In-memory PSP22 ledger standing in for the token contracts. The state is
+        /// thread-local, and every test runs on its own thread.
+        pub(super) mod mock {
+            use super::super::*;
+            use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
+
+            /// Body of a flash swap borrower, given its own account and the loan. Returns
+            /// whether the callback succeeds.
+            type FlashBorrower = Rc<dyn Fn(AccountId, TokenId, Balance) -> bool>;
+
+            /// Hook a token runs after each transfer, given the token, sender, recipient and
+            /// amount.
+            type TransferHook = Rc<dyn Fn(TokenId, AccountId, AccountId, Balance)>;
+
+            thread_local! {
+                static BALANCES: RefCell<BTreeMap<(TokenId, AccountId), Balance>> =
+                    const { RefCell::new(BTreeMap::new()) };
+                static FLASH_BORROWER: RefCell<Option<FlashBorrower>> =
+                    const { RefCell::new(None) };
+                static TRANSFER_HOOK: RefCell<Option<TransferHook>> =
+                    const { RefCell::new(None) };
+            }
+
+            /// Creates `amount` of `token` out of thin air for `account`.
+            pub fn mint(token: TokenId, account: AccountId, amount: Balance) {
+                BALANCES.with(|balances| {
+                    *balances.borrow_mut().entry((token, account)).or_default() += amount;
+                });
+            }
+
+            /// Returns `account`'s balance of `token`.
+            pub fn balance(token: TokenId, account: AccountId) -> Balance {
+                BALANCES.with(|balances| {
+                    balances
+                        .borrow()
+                        .get(&(token, account))
+                        .copied()
+                        .unwrap_or_default()
+                })
+            }
+
+            /// PSP22 `transfer` and `transfer_from`, without allowances, returning what the
+            /// contract call would.
+            pub fn transfer(
+                token: TokenId,
+                from: AccountId,
+                to: AccountId,
+                amount: Balance,
+            ) -> ink::env::Result<ink::MessageResult<Result<(), PSP22Error>>> {
+                let from_balance = balance(token, from);
+                if from_balance < amount {
+                    return Ok(Ok(Err(PSP22Error::InsufficientBalance)));
+                }
+
+                BALANCES.with(|balances| {
+                    let mut balances = balances.borrow_mut();
+                    balances.insert((token, from), from_balance - amount);
+                    *balances.entry((token, to)).or_default() += amount;
+                });
+
+                // Release the slot before running the hook, which may transfer again.
+                let hook = TRANSFER_HOOK.with(|slot| slot.borrow().clone());
+                if let Some(hook) = hook {
+                    hook(token, from, to, amount);
+                }
+                Ok(Ok(Ok(())))
+            }
+
+            /// PSP22 `balance_of`, returning what the contract call would.
+            pub fn balance_of(
+                token: TokenId,
+                account: AccountId,
+            ) -> ink::env::Result<ink::MessageResult<Balance>> {
+                Ok(Ok(balance(token, account)))
+            }
+
+            /// Deploys `borrower` as the callback of every later flash swap.
+            pub fn set_flash_borrower(
+                borrower: impl Fn(AccountId, TokenId, Balance) -> bool + 'static,
+            ) {
+                FLASH_BORROWER.with(|slot| *slot.borrow_mut() = Some(Rc::new(borrower)));
+            }
+
+            /// Makes every token run `hook` after each later successful transfer, as a token with
+            /// transfer hooks would.
+            pub fn set_transfer_hook(
+                hook: impl Fn(TokenId, AccountId, AccountId, Balance) + 'static,
+            ) {
+                TRANSFER_HOOK.with(|slot| *slot.borrow_mut() = Some(Rc::new(hook)));
+            }
+
+            /// `FlashSwapCallback::on_flash_swap` on `callback`, which traps unless a borrower
+            /// was deployed and succeeds.
+            pub fn on_flash_swap(
+                callback: AccountId,
+                token_out: TokenId,
+                amount: Balance,
+                _data: Vec<u8>,
+            ) -> ink::env::Result<ink::MessageResult<()>> {
+                // Release the slot before running the borrower, which may deploy another.
+                let borrower = FLASH_BORROWER.with(|slot| slot.borrow().clone());
+                match borrower {
+                    Some(borrower) if borrower(callback, token_out, amount) => Ok(Ok(())),
+                    _ => Err(ink::env::Error::CalleeTrapped),
+                }
+            }
+        }
+
+        /// Entry counts of the reserves, balances, fees and LP balances mappings, for storage
+        /// audits. `Mapping` cannot be enumerated, so the setters report each write here.
+        pub(super) mod footprint {
+            use std::cell::Cell;
+
+            /// Number of entries held in each of the contract's mappings.
+            #[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+            pub struct StorageFootprint {
+                pub reserves: u32,
+                pub balances: u32,
+                pub fees: u32,
+                pub lp_balances: u32,
+            }
+
+            thread_local! {
+                static FOOTPRINT: Cell<StorageFootprint> = const {
+                    Cell::new(StorageFootprint {
+                        reserves: 0,
+                        balances: 0,
+                        fees: 0,
+                        lp_balances: 0,
+                    })
+                };
+            }
+
+            /// Adjusts the selected counter for an entry that `existed` before a write and
+            /// `exists` after it.
+            pub fn track(
+                counter: fn(&mut StorageFootprint) -> &mut u32,
+                existed: bool,
+                exists: bool,
+            ) {
+                FOOTPRINT.with(|cell| {
+                    let mut footprint = cell.get();
+                    let count = counter(&mut footprint);
+                    match (existed, exists) {
+                        (false, true) => *count += 1,
+                        (true, false) => *count -= 1,
+                        _ => {}
+                    }
+                    cell.set(footprint);
+                });
+            }
+
+            /// Returns the current entry counts.
+            pub fn get() -> StorageFootprint {
+                FOOTPRINT.with(|cell| cell.get())
+            }
+        }
+
+        fn accounts() -> DefaultAccounts<DefaultEnvironment> {
+            test::default_accounts::<DefaultEnvironment>()
+        }
+
+        fn set_caller(account: AccountId) {
+            test::set_caller::<DefaultEnvironment>(account);
+        }
+
+        fn token_a() -> TokenId {
+            AccountId::from(TOKEN_A)
+        }
+
+        fn token_b() -> TokenId {
+            AccountId::from(TOKEN_B)
+        }
+
+        /// Instantiates the contract from Alice with an A/B pool, id 0, at the default fee.
+        fn setup() -> SimpleContract {
+            test::set_callee::<DefaultEnvironment>(AccountId::from(CONTRACT));
+            set_caller(accounts().alice);
+            SimpleContract::new(token_a(), token_b(), FEE_BPS).unwrap()
+        }
+
+        /// Mints the amounts to `account` and deposits them into pool 0 from it.
+        fn add_liquidity(
+            contract: &mut SimpleContract,
+            account: AccountId,
+            amount_a: Balance,
+            amount_b: Balance,
+        ) -> Result<Balance, Error> {
+            mock::mint(token_a(), account, amount_a);
+            mock::mint(token_b(), account, amount_b);
+            set_caller(account);
+            contract.add_liquidity(0, amount_a, amount_b, NO_DEADLINE)
+        }
+
+        type Event = <SimpleContract as ink::reflect::ContractEventBase>::Type;
+
+        /// Decodes the events emitted so far.
+        fn emitted_events() -> Vec<Event> {
+            test::recorded_events()
+                .map(|event| Event::decode(&mut &event.data[..]).expect("Invalid event data"))
+                .collect()
+        }
+
+        /// Mints `amount` of `token_in` to `account` and swaps it in pool 0 from it.
+        fn swap(
+            contract: &mut SimpleContract,
+            account: AccountId,
+            token_in: TokenId,
+            amount: Balance,
+        ) -> Result<Balance, Error> {
+            mock::mint(token_in, account, amount);
+            set_caller(account);
+            contract.swap(0, token_in, amount, 0, NO_DEADLINE)
+        }
+
+        /// A second instance over the contract's storage, as a re-entrant call would load it.
+        /// Fields of the root struct start afresh, but mappings and the reentrancy lock are
+        /// shared with the instance under test.
+        ///
+        /// Reentrancy scenarios are written as a mock callback, such as a flash swap borrower,
+        /// that calls a message on this handle and records the result for the test to assert.
+        fn reentrant_handle() -> SimpleContract {
+            SimpleContract::with_storage(MINIMUM_LIQUIDITY)
+        }
+
+        #[ink::test]
+        fn two_sided_quote_matches_both_swap_directions() {
+            let mut contract = setup();
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 4_000_000).unwrap();
+
+            let (quote_0, quote_1) = contract.get_two_sided_quote(0, 10_000);
+            assert_eq!(quote_0, contract.quote_swap(0, token_a(), 10_000));
+            assert_eq!(quote_1, contract.quote_swap(0, token_b(), 10_000));
+            // Each direction trades against the other reserve, so they differ.
+            assert!(quote_0 > quote_1);
+        }
+
+        #[ink::test]
+        fn reset_fees_zeroes_fees_and_leaves_reserves() {
+            let mut contract = setup();
+            // The protocol keeps the whole fee.
+            contract.set_protocol_fee_bps(10_000).unwrap();
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 1_000_000).unwrap();
+            swap(&mut contract, accounts().bob, token_a(), 100_000).unwrap();
+            let fees = contract.get_fees(0, token_a());
+            let reserves = (
+                contract.get_reserve(0, token_a()),
+                contract.get_reserve(0, token_b()),
+            );
+            assert!(fees > 0);
+
+            set_caller(accounts().alice);
+            assert_eq!(contract.reset_fees(0, token_a()), Ok(()));
+
+            assert_eq!(contract.get_fees(0, token_a()), 0);
+            assert_eq!(
+                (
+                    contract.get_reserve(0, token_a()),
+                    contract.get_reserve(0, token_b())
+                ),
+                reserves
+            );
+            assert!(matches!(
+                emitted_events().last(),
+                Some(Event::FeesReset(FeesReset { previous, .. })) if *previous == fees
+            ));
+        }
+
+        #[ink::test]
+        fn reset_fees_rejects_non_owner_and_foreign_tokens() {
+            let mut contract = setup();
+            assert_eq!(
+                contract.reset_fees(0, AccountId::from([0xD0; 32])),
+                Err(Error::TokenNotInPool)
+            );
+            assert_eq!(contract.reset_fees(1, token_a()), Err(Error::PoolNotFound));
+
+            set_caller(accounts().bob);
+            assert_eq!(contract.reset_fees(0, token_a()), Err(Error::NotOwner));
+            assert!(emitted_events()
+                .iter()
+                .all(|event| !matches!(event, Event::FeesReset(_))));
+        }
+
+        #[ink::test]
+        fn swap_counts_are_tracked_per_account() {
+            let mut contract = setup();
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 1_000_000).unwrap();
+
+            for _ in 0..3 {
+                swap(&mut contract, accounts().bob, token_a(), 1_000).unwrap();
+            }
+            swap(&mut contract, accounts().charlie, token_b(), 1_000).unwrap();
+            // A failed swap is not counted.
+            assert_eq!(
+                swap(&mut contract, accounts().charlie, token_b(), 1),
+                Err(Error::AmountTooSmall)
+            );
+
+            assert_eq!(contract.get_account_swap_count(accounts().bob), 3);
+            assert_eq!(contract.get_account_swap_count(accounts().charlie), 1);
+            assert_eq!(contract.get_account_swap_count(accounts().alice), 0);
+        }
+
+        #[ink::test]
+        fn add_liquidity_full_credits_recipient_within_minimums_and_deadline() {
+            let mut contract = setup();
+            let (alice, bob) = (accounts().alice, accounts().bob);
+            mock::mint(token_a(), alice, 1_100_000);
+            mock::mint(token_b(), alice, 1_200_000);
+            set_caller(alice);
+
+            let minted = contract
+                .add_liquidity_full(0, 1_000_000, 1_000_000, 0, 0, bob, NO_DEADLINE)
+                .unwrap();
+            assert_eq!(minted, 1_000_000 - MINIMUM_LIQUIDITY);
+            assert_eq!(contract.lp_balance_of(bob, 0), minted);
+            assert_eq!(contract.lp_balance_of(alice, 0), 0);
+            assert_eq!(mock::balance(token_a(), alice), 100_000);
+
+            // The desired amounts are adjusted to 100_000 of each, below the token_1 minimum.
+            assert_eq!(
+                contract.add_liquidity_full(0, 100_000, 200_000, 0, 150_000, bob, NO_DEADLINE),
+                Err(Error::InsufficientAmount)
+            );
+            assert_eq!(
+                contract.add_liquidity_full(
+                    0,
+                    100_000,
+                    200_000,
+                    100_000,
+                    100_000,
+                    bob,
+                    NO_DEADLINE
+                ),
+                Ok(100_000)
+            );
+
+            test::advance_block::<DefaultEnvironment>();
+            test::advance_block::<DefaultEnvironment>();
+            assert_eq!(
+                contract.add_liquidity_full(0, 1, 1, 0, 0, bob, 1),
+                Err(Error::Expired)
+            );
+        }
+
+        #[ink::test]
+        fn reserve_ratio_bps_reports_token_0_share() {
+            let mut contract = setup();
+            assert_eq!(contract.get_reserve_ratio_bps(0), 0);
+
+            add_liquidity(&mut contract, accounts().alice, 3_000_000, 1_000_000).unwrap();
+            assert_eq!(contract.get_reserve_ratio_bps(0), 7_500);
+
+            // Reserves whose sum or scaled share overflows still report the share.
+            contract.set_reserve(0, token_a(), Balance::MAX / 2);
+            contract.set_reserve(0, token_b(), Balance::MAX / 2);
+            assert_eq!(contract.get_reserve_ratio_bps(0), 5_000);
+        }
+
+        #[ink::test]
+        fn reentrant_swap_from_flash_callback_is_blocked() {
+            let mut contract = setup();
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 1_000_000).unwrap();
+
+            let reentry = Rc::new(RefCell::new(None));
+            let recorded = reentry.clone();
+            mock::set_flash_borrower(move |borrower, token_out, amount| {
+                mock::mint(token_a(), borrower, 1_000);
+                set_caller(borrower);
+                *recorded.borrow_mut() =
+                    Some(reentrant_handle().swap(0, token_a(), 1_000, 0, NO_DEADLINE));
+
+                // Repay the loan with the pool fee on top.
+                mock::mint(token_out, borrower, amount / 100);
+                mock::transfer(
+                    token_out,
+                    borrower,
+                    AccountId::from(CONTRACT),
+                    amount + amount / 100,
+                )
+                .is_ok_and(|result| matches!(result, Ok(Ok(()))))
+            });
+
+            set_caller(accounts().bob);
+            assert_eq!(
+                contract.flash_swap(0, token_b(), 10_000, accounts().django, Vec::new()),
+                Ok(())
+            );
+            assert_eq!(*reentry.borrow(), Some(Err(Error::Reentrancy)));
+
+            // The lock is released once the flash swap returns.
+            assert!(swap(&mut reentrant_handle(), accounts().bob, token_a(), 1_000).is_ok());
+        }
+
+        #[ink::test]
+        fn minimum_received_applies_slippage_tolerance() {
+            let mut contract = setup();
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 1_000_000).unwrap();
+            let quote = contract.quote_swap(0, token_a(), 10_000);
+
+            for slippage_bps in [0, 50, 100, 10_000] {
+                assert_eq!(
+                    contract.get_minimum_received(0, token_a(), 10_000, slippage_bps),
+                    quote * (10_000 - slippage_bps as Balance) / 10_000
+                );
+            }
+            // Tolerances above 100% floor at zero.
+            assert_eq!(
+                contract.get_minimum_received(0, token_a(), 10_000, 20_000),
+                0
+            );
+        }
+
+        #[ink::test]
+        fn sole_lp_exit_returns_the_deposit_plus_its_fee_share() {
+            let mut contract = setup();
+            let (alice, bob) = (accounts().alice, accounts().bob);
+            let deposit = 1_000_000_000_000;
+            let liquidity = add_liquidity(&mut contract, alice, deposit, deposit).unwrap();
+            for _ in 0..5 {
+                swap(&mut contract, bob, token_a(), 1_000_000_000).unwrap();
+                swap(&mut contract, bob, token_b(), 1_000_000_000).unwrap();
+            }
+            let fees = (
+                contract.get_lp_fees(0, token_a()),
+                contract.get_lp_fees(0, token_b()),
+            );
+            let reserves = (
+                contract.get_reserve(0, token_a()),
+                contract.get_reserve(0, token_b()),
+            );
+            assert_eq!(contract.get_fees(0, token_a()), 0);
+
+            set_caller(alice);
+            let (amount_a, amount_b) = contract.remove_liquidity(0, liquidity).unwrap();
+
+            // The sole LP gets its share of the reserves plus the same share of the fees; the
+            // locked minimum liquidity keeps the rest of both in the pool.
+            let supply = liquidity + MINIMUM_LIQUIDITY;
+            let fee_share = (fees.0 * liquidity / supply, fees.1 * liquidity / supply);
+            assert_eq!(amount_a, reserves.0 * liquidity / supply + fee_share.0);
+            assert_eq!(amount_b, reserves.1 * liquidity / supply + fee_share.1);
+            assert!(amount_a > deposit && amount_b > deposit);
+            assert_eq!(mock::balance(token_a(), alice), amount_a);
+            assert_eq!(contract.get_lp_fees(0, token_a()), fees.0 - fee_share.0);
+            assert_eq!(contract.get_unclaimed_fees(0, alice), (0, 0));
+        }
+
+        #[ink::test]
+        fn exit_claims_the_fees_before_removing_the_liquidity() {
+            let mut contract = setup();
+            let (alice, bob, charlie) = (accounts().alice, accounts().bob, accounts().charlie);
+            add_liquidity(&mut contract, alice, 1_000_000, 1_000_000).unwrap();
+            let liquidity = add_liquidity(&mut contract, bob, 500_000, 500_000).unwrap();
+            swap(&mut contract, charlie, token_a(), 100_000).unwrap();
+            swap(&mut contract, charlie, token_b(), 100_000).unwrap();
+
+            let fees = contract.get_unclaimed_fees(0, bob);
+            assert!(fees.0 > 0 && fees.1 > 0);
+            let supply = contract.total_supply(0);
+            let principal = (
+                contract.get_reserve(0, token_a()) * liquidity / supply,
+                contract.get_reserve(0, token_b()) * liquidity / supply,
+            );
+            let held = (mock::balance(token_a(), bob), mock::balance(token_b(), bob));
+
+            set_caller(bob);
+            assert_eq!(
+                contract.remove_liquidity(0, liquidity),
+                Ok((principal.0 + fees.0, principal.1 + fees.1))
+            );
+            assert_eq!(mock::balance(token_a(), bob), held.0 + principal.0 + fees.0);
+            assert_eq!(mock::balance(token_b(), bob), held.1 + principal.1 + fees.1);
+
+            let events = emitted_events();
+            assert!(matches!(
+                &events[events.len() - 2..],
+                [
+                    Event::FeesClaimed(FeesClaimed { amounts, account: claimer, .. }),
+                    Event::LiquidityRemoved(LiquidityRemoved { amounts: removed, account, .. }),
+                ] if *amounts == fees && *claimer == bob && *removed == principal && *account == bob
+            ));
+        }
+
+        #[ink::test]
+        fn lp_fees_are_earned_only_while_the_lp_tokens_are_held() {
+            let mut contract = setup();
+            let (alice, bob, charlie) = (accounts().alice, accounts().bob, accounts().charlie);
+            add_liquidity(&mut contract, alice, 1_000_000, 1_000_000).unwrap();
+            swap(&mut contract, charlie, token_a(), 100_000).unwrap();
+
+            // Fees accrued before Bob joined are Alice's alone.
+            let bob_liquidity = add_liquidity(&mut contract, bob, 110_000, 110_000).unwrap();
+            assert_eq!(contract.get_unclaimed_fees(0, bob), (0, 0));
+            assert_eq!(contract.get_unclaimed_fees(0, alice), (299, 0));
+
+            // Later fees are shared in proportion to the LP tokens.
+            swap(&mut contract, charlie, token_b(), 100_000).unwrap();
+            let supply = contract.total_supply(0);
+            let (_, bob_fee_b) = contract.get_unclaimed_fees(0, bob);
+            assert_eq!(bob_fee_b, 300 * bob_liquidity / supply);
+
+            // Bob earned no fees of token_a, so he gets only his share of its reserve.
+            let reserve_a = contract.get_reserve(0, token_a());
+            set_caller(bob);
+            let held_b = mock::balance(token_b(), bob);
+            let (amount_a, amount_b) = contract.remove_liquidity(0, bob_liquidity).unwrap();
+            assert_eq!(amount_a, reserve_a * bob_liquidity / supply);
+            assert_eq!(mock::balance(token_b(), bob), held_b + amount_b);
+            assert_eq!(contract.get_unclaimed_fees(0, bob), (0, 0));
+            assert_eq!(contract.get_unclaimed_fees(0, alice).0, 299);
+        }
+
+        #[ink::test]
+        fn check_add_liquidity_suggests_optimal_counter_amount() {
+            let mut contract = setup();
+            // An empty pool accepts any ratio.
+            assert_eq!(contract.check_add_liquidity(0, 1, 7), Ok(7));
+
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 2_000_000).unwrap();
+            assert_eq!(
+                contract.check_add_liquidity(0, 10_000, 15_000),
+                Err(Error::RatioMismatch {
+                    optimal_amount_1: 20_000
+                })
+            );
+            assert_eq!(contract.check_add_liquidity(0, 10_000, 20_000), Ok(20_000));
+        }
+
+        #[ink::test]
+        fn get_balance_matches_balance_of_for_caller() {
+            let mut contract = setup();
+            let alice = accounts().alice;
+            add_liquidity(&mut contract, alice, 1_000_000, 3_000_000).unwrap();
+
+            set_caller(alice);
+            for token in [token_a(), token_b()] {
+                assert_eq!(
+                    contract.get_balance(0, token),
+                    contract.balance_of(alice, 0, token)
+                );
+            }
+            assert_eq!(contract.get_balance(0, token_b()), 3_000_000);
+        }
+
+        #[ink::test]
+        fn constructors_create_the_first_pool_or_reject_identical_tokens() {
+            test::set_callee::<DefaultEnvironment>(AccountId::from(CONTRACT));
+            let contract = SimpleContract::default().unwrap();
+            let pool = contract.get_pool(0).unwrap();
+            assert_eq!(
+                (pool.token_0, pool.token_1),
+                (AccountId::from([0; 32]), AccountId::from([1; 32]))
+            );
+            assert_eq!(contract.get_fee_bps(0), FEE_BPS);
+            assert_eq!(contract.get_pool_count(), 1);
+
+            assert!(matches!(
+                SimpleContract::new(token_a(), token_a(), FEE_BPS),
+                Err(Error::IdenticalTokens)
+            ));
+        }
+
+        #[ink::test]
+        fn swap_below_min_output_writes_nothing() {
+            let mut contract = setup();
+            let (alice, bob) = (accounts().alice, accounts().bob);
+            add_liquidity(&mut contract, alice, 1_000_000, 1_000_000).unwrap();
+            swap(&mut contract, bob, token_a(), 10_000).unwrap();
+            mock::mint(token_a(), bob, 10_000);
+
+            let snapshot = |contract: &SimpleContract| {
+                [token_a(), token_b()].map(|token| {
+                    (
+                        contract.get_reserve(0, token),
+                        contract.balance_of(bob, 0, token),
+                        contract.get_fees(0, token),
+                        contract.fees_accrued.get((0, token)).unwrap_or_default(),
+                        mock::balance(token, bob),
+                    )
+                })
+            };
+            let before = snapshot(&contract);
+            let swap_count = contract.get_account_swap_count(bob);
+            let event_count = emitted_events().len();
+
+            // The unit test environment does not roll back storage on an error, so this checks
+            // that the swap fails before its first write rather than relying on the revert.
+            let quote = contract.quote_swap(0, token_a(), 10_000);
+            assert_eq!(
+                contract.swap(0, token_a(), 10_000, quote + 1, NO_DEADLINE),
+                Err(Error::SlippageExceeded)
+            );
+
+            assert_eq!(snapshot(&contract), before);
+            assert_eq!(contract.get_account_swap_count(bob), swap_count);
+            assert_eq!(emitted_events().len(), event_count);
+        }
+
+        #[ink::test]
+        fn withdraw_all_fees_sweeps_both_tokens_at_once() {
+            let mut contract = setup();
+            // The protocol keeps the whole fee.
+            contract.set_protocol_fee_bps(10_000).unwrap();
+            let (alice, bob, eve) = (accounts().alice, accounts().bob, accounts().eve);
+            add_liquidity(&mut contract, alice, 1_000_000, 1_000_000).unwrap();
+            swap(&mut contract, bob, token_a(), 100_000).unwrap();
+            swap(&mut contract, bob, token_b(), 100_000).unwrap();
+            let fees = (
+                contract.get_fees(0, token_a()),
+                contract.get_fees(0, token_b()),
+            );
+            assert!(fees.0 > 0 && fees.1 > 0);
+
+            set_caller(alice);
+            contract.set_protocol_fee_recipient(eve).unwrap();
+            assert_eq!(contract.withdraw_all_fees(0), Ok(fees));
+
+            assert_eq!(
+                (
+                    contract.get_fees(0, token_a()),
+                    contract.get_fees(0, token_b())
+                ),
+                (0, 0)
+            );
+            assert_eq!(
+                (mock::balance(token_a(), eve), mock::balance(token_b(), eve)),
+                fees
+            );
+        }
+
+        #[ink::test]
+        fn price_limit_matches_the_equivalent_minimum_output() {
+            let mut contract = setup();
+            let bob = accounts().bob;
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 3_000_000).unwrap();
+            mock::mint(token_a(), bob, 20_000);
+            set_caller(bob);
+
+            let quote = contract.get_amount_out(0, token_a(), 10_000).unwrap();
+            let price = 10_000 * PRECISION / quote;
+
+            // Each limit rejects a trade just outside it without writing anything, then admits
+            // the same trade at its boundary.
+            assert_eq!(
+                contract.swap_with_price_limit(0, token_a(), 10_000, price - 1),
+                Err(Error::PriceLimitExceeded)
+            );
+            assert_eq!(
+                contract.swap(0, token_a(), 10_000, quote + 1, NO_DEADLINE),
+                Err(Error::SlippageExceeded)
+            );
+            assert_eq!(contract.get_account_swap_count(bob), 0);
+
+            assert_eq!(
+                contract.swap_with_price_limit(0, token_a(), 10_000, price),
+                Ok(quote)
+            );
+            let quote = contract.get_amount_out(0, token_a(), 10_000).unwrap();
+            assert_eq!(
+                contract.swap(0, token_a(), 10_000, quote, NO_DEADLINE),
+                Ok(quote)
+            );
+        }
+
+        #[ink::test]
+        fn fee_apr_annualizes_fees_since_the_checkpoint() {
+            let mut contract = setup();
+            let alice = accounts().alice;
+            add_liquidity(&mut contract, alice, 1_000_000, 1_000_000).unwrap();
+
+            set_caller(accounts().bob);
+            assert_eq!(contract.checkpoint_fees(0), Err(Error::NotOwner));
+            set_caller(alice);
+            contract.checkpoint_fees(0).unwrap();
+            assert_eq!(contract.get_fee_apr_bps(0), 0);
+
+            // 20 units of fees over 1000 blocks on a pool worth 2_000_000 is 1051.2 units a
+            // year, or 5.256%.
+            for _ in 0..1000 {
+                test::advance_block::<DefaultEnvironment>();
+            }
+            contract.fees_accrued.insert((0, token_a()), &10);
+            contract.fees_accrued.insert((0, token_b()), &10);
+            assert_eq!(contract.get_fee_apr_bps(0), 525);
+
+            // LPs only earn what the protocol leaves them.
+            contract.set_protocol_fee_bps(5_000).unwrap();
+            assert_eq!(contract.get_fee_apr_bps(0), 262);
+            contract.set_protocol_fee_bps(10_000).unwrap();
+            assert_eq!(contract.get_fee_apr_bps(0), 0);
+        }
+
+        #[ink::test]
+        fn emergency_pause_freezes_everything_until_lifted() {
+            let mut contract = setup();
+            let (alice, bob, eve) = (accounts().alice, accounts().bob, accounts().eve);
+            let liquidity = add_liquidity(&mut contract, alice, 1_000_000, 1_000_000).unwrap();
+            swap(&mut contract, bob, token_a(), 10_000).unwrap();
+
+            set_caller(alice);
+            contract.set_guardian(eve).unwrap();
+            set_caller(eve);
+            contract.emergency_pause(7).unwrap();
+            assert!(contract.is_emergency_paused());
+            assert_eq!(contract.get_pause_reason(), 7);
+
+            // Unlike the operational pause, the freeze also blocks exits and fee collection.
+            assert_eq!(
+                swap(&mut contract, bob, token_a(), 10_000),
+                Err(Error::EmergencyPaused)
+            );
+            assert_eq!(
+                add_liquidity(&mut contract, bob, 10_000, 10_000),
+                Err(Error::EmergencyPaused)
+            );
+            set_caller(alice);
+            assert_eq!(
+                contract.remove_liquidity(0, liquidity),
+                Err(Error::EmergencyPaused)
+            );
+            assert_eq!(contract.withdraw_all_fees(0), Err(Error::EmergencyPaused));
+            assert_eq!(contract.skim(token_a(), alice), Err(Error::EmergencyPaused));
+
+            set_caller(eve);
+            contract.emergency_unpause().unwrap();
+            assert!(!contract.is_emergency_paused());
+            assert_eq!(contract.get_pause_reason(), 0);
+            set_caller(alice);
+            assert!(contract.remove_liquidity(0, liquidity).is_ok());
+        }
+
+        #[ink::test]
+        fn emergency_pause_is_guardian_only() {
+            let mut contract = setup();
+            assert_eq!(contract.emergency_pause(1), Err(Error::NotGuardian));
+            contract.set_guardian(accounts().eve).unwrap();
+
+            // Not even the owner can trigger it.
+            assert_eq!(contract.emergency_pause(1), Err(Error::NotGuardian));
+            assert!(!contract.is_emergency_paused());
+        }
+
+        #[ink::test]
+        fn emergency_unpause_is_guardian_only() {
+            let mut contract = setup();
+            contract.set_guardian(accounts().eve).unwrap();
+            set_caller(accounts().eve);
+            contract.emergency_pause(1).unwrap();
+
+            set_caller(accounts().alice);
+            assert_eq!(contract.emergency_unpause(), Err(Error::NotGuardian));
+            assert!(contract.is_emergency_paused());
+        }
+
+        #[ink::test]
+        fn guardian_cannot_be_the_owner() {
+            let mut contract = setup();
+            assert_eq!(
+                contract.set_guardian(accounts().alice),
+                Err(Error::GuardianIsOwner)
+            );
+            assert_eq!(contract.get_config(0).guardian, None);
+
+            set_caller(accounts().bob);
+            assert_eq!(contract.set_guardian(accounts().bob), Err(Error::NotOwner));
+        }
+
+        #[ink::test]
+        fn rescue_native_is_frozen_by_the_guardian() {
+            let mut contract = setup();
+            contract.set_guardian(accounts().eve).unwrap();
+            set_caller(accounts().eve);
+            contract.emergency_pause(1).unwrap();
+
+            set_caller(accounts().alice);
+            assert_eq!(
+                contract.rescue_native(accounts().alice, 0),
+                Err(Error::EmergencyPaused)
+            );
+        }
+
+        #[ink::test]
+        fn storage_footprint_tracks_insertions_and_full_exits() {
+            let mut contract = setup();
+            let (alice, bob) = (accounts().alice, accounts().bob);
+            assert_eq!(footprint::get(), footprint::StorageFootprint::default());
+
+            add_liquidity(&mut contract, alice, 1_000_000, 1_000_000).unwrap();
+            let after_alice = footprint::get();
+            assert_eq!(after_alice.reserves, 2);
+            assert_eq!(after_alice.balances, 2);
+
+            let liquidity = add_liquidity(&mut contract, bob, 500_000, 500_000).unwrap();
+            assert_eq!(footprint::get().balances, after_alice.balances + 2);
+            assert_eq!(footprint::get().lp_balances, after_alice.lp_balances + 1);
+
+            set_caller(bob);
+            contract.remove_liquidity(0, liquidity).unwrap();
+            let after_exit = footprint::get();
+            assert_eq!(after_exit.balances, after_alice.balances);
+            assert_eq!(after_exit.lp_balances, after_alice.lp_balances);
+
+            // Rewriting existing entries adds none.
+            swap(&mut contract, bob, token_a(), 10_000).unwrap();
+            let after_swap = footprint::get();
+            assert_eq!(after_swap.reserves, 2);
+            assert_eq!(after_swap.fees, 1);
+            assert_eq!(after_swap.balances, after_alice.balances);
+        }
+
+        #[ink::test]
+        fn full_exit_removes_the_position_entries() {
+            let mut contract = setup();
+            let (alice, bob) = (accounts().alice, accounts().bob);
+            add_liquidity(&mut contract, alice, 1_000_000, 1_000_000).unwrap();
+            let liquidity = add_liquidity(&mut contract, bob, 500_000, 500_000).unwrap();
+            let before = footprint::get();
+
+            // A partial exit keeps the entries.
+            set_caller(bob);
+            contract.remove_liquidity(0, liquidity / 2).unwrap();
+            assert_eq!(footprint::get(), before);
+
+            contract
+                .remove_liquidity(0, liquidity - liquidity / 2)
+                .unwrap();
+            assert!(!contract.lp_balances.contains((bob, 0)));
+            assert!(!contract.balances.contains((bob, 0, token_a())));
+            assert!(!contract.balances.contains((bob, 0, token_b())));
+            assert_eq!(footprint::get().lp_balances, before.lp_balances - 1);
+            assert_eq!(footprint::get().balances, before.balances - 2);
+        }
+
+        #[ink::test]
+        fn optimal_arb_amount_moves_the_price_to_the_target() {
+            let mut contract = setup();
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 4_000_000).unwrap();
+            let target = 3 * PRECISION;
+
+            let amount = contract.optimal_arb_amount(0, token_a(), target);
+            swap(&mut contract, accounts().bob, token_a(), amount).unwrap();
+
+            // Rounding lands the price within a hundredth of a percent of the target.
+            let price = contract.get_price(0, token_a());
+            assert!(price.abs_diff(target) <= target / 10_000);
+            // Nothing is left to arbitrage once the price is at or below the target.
+            assert_eq!(contract.optimal_arb_amount(0, token_a(), price), 0);
+            assert_eq!(contract.optimal_arb_amount(0, token_a(), 4 * PRECISION), 0);
+        }
+
+        #[ink::test]
+        fn swapped_event_reports_the_post_trade_price() {
+            let mut contract = setup();
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 2_000_000).unwrap();
+
+            for token_in in [token_a(), token_b()] {
+                swap(&mut contract, accounts().bob, token_in, 25_000).unwrap();
+
+                let expected = contract.get_price(0, token_in);
+                assert!(matches!(
+                    emitted_events().last(),
+                    Some(Event::Swapped(Swapped { new_spot_price, .. })) if *new_spot_price == expected
+                ));
+            }
+        }
+
+        #[ink::test]
+        fn protocol_fees_go_to_the_protocol_recipient() {
+            let mut contract = setup();
+            // The protocol keeps the whole fee.
+            contract.set_protocol_fee_bps(10_000).unwrap();
+            let (alice, bob, eve) = (accounts().alice, accounts().bob, accounts().eve);
+            assert_eq!(contract.get_protocol_fee_recipient(), alice);
+            add_liquidity(&mut contract, alice, 1_000_000, 1_000_000).unwrap();
+            swap(&mut contract, bob, token_a(), 100_000).unwrap();
+            let fee = contract.get_fees(0, token_a());
+            let reserves = (
+                contract.get_reserve(0, token_a()),
+                contract.get_reserve(0, token_b()),
+            );
+            let owner_balance = mock::balance(token_a(), alice);
+
+            set_caller(alice);
+            contract.set_protocol_fee_recipient(eve).unwrap();
+            assert_eq!(contract.get_protocol_fee_recipient(), eve);
+            assert_eq!(contract.withdraw_all_fees(0), Ok((fee, 0)));
+
+            // Only the recipient is paid, and collection leaves the LPs' reserves alone.
+            assert_eq!(mock::balance(token_a(), eve), fee);
+            assert_eq!(mock::balance(token_a(), alice), owner_balance);
+            assert_eq!(
+                (
+                    contract.get_reserve(0, token_a()),
+                    contract.get_reserve(0, token_b())
+                ),
+                reserves
+            );
+        }
+
+        #[ink::test]
+        fn protocol_fee_recipient_is_set_by_the_owner_only() {
+            let mut contract = setup();
+            set_caller(accounts().bob);
+            assert_eq!(
+                contract.set_protocol_fee_recipient(accounts().bob),
+                Err(Error::NotOwner)
+            );
+            assert_eq!(contract.get_protocol_fee_recipient(), accounts().alice);
+        }
+
+        #[ink::test]
+        fn implied_reserve_round_trips_with_get_price() {
+            let mut contract = setup();
+            add_liquidity(&mut contract, accounts().alice, 1_234_567, 7_654_321).unwrap();
+
+            for (token, other) in [(token_a(), token_b()), (token_b(), token_a())] {
+                let price = contract.get_price(0, token);
+                assert_eq!(
+                    contract.implied_reserve(0, token, contract.get_reserve(0, token), price),
+                    contract.get_reserve(0, other)
+                );
+            }
+        }
+
+        /// Seeds the reserve of `near_max` close to `Balance::MAX` and checks that a deposit
+        /// overflowing it fails without writing either token's reserve or balance. The other
+        /// reserve is large enough for the deposit to keep a nonzero amount of both tokens at
+        /// the pool's ratio.
+        fn assert_overflowing_deposit_writes_nothing(near_max: TokenId) {
+            let mut contract = setup();
+            let bob = accounts().bob;
+            let other = contract.expect_counterpart(0, near_max);
+            contract.set_reserve(0, near_max, Balance::MAX - 10);
+            contract.set_reserve(0, other, Balance::MAX / 2);
+
+            let snapshot = |contract: &SimpleContract| {
+                [token_a(), token_b()].map(|token| {
+                    (
+                        contract.get_reserve(0, token),
+                        contract.balance_of(bob, 0, token),
+                    )
+                })
+            };
+            let before = snapshot(&contract);
+
+            assert_eq!(
+                add_liquidity(&mut contract, bob, 1_000_000, 1_000_000),
+                Err(Error::Overflow)
+            );
+            assert_eq!(snapshot(&contract), before);
+            assert_eq!(contract.lp_balance_of(bob, 0), 0);
+            assert_eq!(contract.total_supply(0), 0);
+            assert_eq!(mock::balance(token_a(), bob), 1_000_000);
+            assert_eq!(mock::balance(token_b(), bob), 1_000_000);
+        }
+
+        #[ink::test]
+        fn add_liquidity_overflow_on_token_0_writes_nothing() {
+            assert_overflowing_deposit_writes_nothing(token_a());
+        }
+
+        #[ink::test]
+        fn add_liquidity_overflow_on_token_1_writes_nothing() {
+            // token_0's new reserve is computed successfully before token_1's overflows.
+            assert_overflowing_deposit_writes_nothing(token_b());
+        }
+
+        #[ink::test]
+        fn created_at_records_the_block_timestamp() {
+            test::set_block_timestamp::<DefaultEnvironment>(1_700_000_000_000);
+            let mut contract = setup();
+            assert_eq!(contract.get_created_at(0), Some(1_700_000_000_000));
+
+            test::advance_block::<DefaultEnvironment>();
+            let now = ink::env::block_timestamp::<DefaultEnvironment>();
+            let pool_id = contract
+                .create_pool(token_a(), AccountId::from([0xD0; 32]), FEE_BPS)
+                .unwrap();
+            assert_eq!(contract.get_created_at(pool_id), Some(now));
+            assert_eq!(contract.get_created_at(pool_id + 1), None);
+        }
+
+        #[ink::test]
+        fn output_at_the_reserve_boundary_is_rejected() {
+            let mut contract = setup();
+            let bob = accounts().bob;
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 1_000_000).unwrap();
+            set_caller(bob);
+
+            for amount_out in [1_000_000, 1_000_001] {
+                assert_eq!(
+                    contract.swap_for_exact_out(0, token_b(), amount_out, Balance::MAX),
+                    Err(Error::InsufficientReserve)
+                );
+            }
+            assert_eq!(contract.get_reserve(0, token_b()), 1_000_000);
+
+            // An arbitrarily large input only approaches the reserve.
+            let amount_out = swap(&mut contract, bob, token_a(), 10u128.pow(30)).unwrap();
+            assert!(amount_out < 1_000_000);
+            assert!(contract.get_reserve(0, token_b()) > 0);
+        }
+
+        #[ink::test]
+        fn impermanent_loss_follows_the_price_move() {
+            let mut contract = setup();
+            let alice = accounts().alice;
+            add_liquidity(&mut contract, alice, 1_000_000, 1_000_000).unwrap();
+            let entry_price = contract.get_price(0, token_a());
+            assert_eq!(contract.estimate_il_bps(0, alice, entry_price), 0);
+
+            // Selling token_b until token_a is worth about four times as much costs a holder of
+            // the position about 20% against holding: 2 * sqrt(4) / (1 + 4) - 1.
+            swap(&mut contract, accounts().bob, token_b(), 1_000_000).unwrap();
+            let ratio = contract.get_price(0, token_a()) as f64 / entry_price as f64;
+            let expected = (2.0 * ratio.sqrt() / (1.0 + ratio) - 1.0) * 10_000.0;
+            let il = contract.estimate_il_bps(0, alice, entry_price);
+            assert!(il < 0);
+            assert!((il as f64 - expected).abs() <= 1.0);
+
+            // Accounts without a position have nothing at stake.
+            assert_eq!(contract.estimate_il_bps(0, accounts().eve, entry_price), 0);
+        }
+
+        #[ink::test]
+        fn config_reflects_each_setter() {
+            test::set_callee::<DefaultEnvironment>(AccountId::from(CONTRACT));
+            let (alice, eve, frank) = (accounts().alice, accounts().eve, accounts().frank);
+            set_caller(alice);
+            let mut contract =
+                SimpleContract::new_with_minimum_liquidity(token_a(), token_b(), FEE_BPS, 5_000)
+                    .unwrap();
+
+            contract.set_swap_rate_limit(3).unwrap();
+            contract.set_paused(true).unwrap();
+            contract.set_guardian(eve).unwrap();
+            contract.set_protocol_fee_recipient(frank).unwrap();
+            contract.set_protocol_fee_bps(2_500).unwrap();
+            set_caller(eve);
+            contract.emergency_pause(1).unwrap();
+
+            assert_eq!(
+                contract.get_config(0),
+                Config {
+                    swap_rate_limit: 3,
+                    paused: true,
+                    emergency_paused: true,
+                    owner: alice,
+                    guardian: Some(eve),
+                    protocol_fee_recipient: frank,
+                    protocol_fee_bps: 2_500,
+                    min_initial_liquidity: 5_000,
+                    fee_bps: FEE_BPS,
+                }
+            );
+        }
+
+        #[ink::test]
+        fn dust_swap_is_rejected_without_an_event() {
+            let mut contract = setup();
+            let bob = accounts().bob;
+            add_liquidity(&mut contract, accounts().alice, 1_000_000_000_000, 1_000).unwrap();
+            let event_count = emitted_events().len();
+
+            // 997 of token_a after the fee buys 997 * 1000 / (10^12 + 997) of token_b, i.e. none.
+            assert_eq!(
+                swap(&mut contract, bob, token_a(), 1_000),
+                Err(Error::ZeroOutput)
+            );
+
+            assert_eq!(emitted_events().len(), event_count);
+            assert_eq!(contract.get_reserve(0, token_a()), 1_000_000_000_000);
+            assert_eq!(contract.get_fees(0, token_a()), 0);
+            assert_eq!(mock::balance(token_a(), bob), 1_000);
+        }
+
+        /// Checks `r*r <= n < (r+1)*(r+1)` for the root `r` of `n = a * b`, at full width.
+        fn assert_floor_root(a: u128, b: u128, root: u128) {
+            let product = SimpleContract::wide_mul(a, b);
+            assert!(SimpleContract::wide_mul(root, root) <= product, "{a} * {b}");
+            if let Some(next) = root.checked_add(1) {
+                assert!(SimpleContract::wide_mul(next, next) > product, "{a} * {b}");
+            }
+        }
+
+        /// Inputs spread over every magnitude: powers of two and perfect squares with their
+        /// neighbours, followed by a fixed pseudo-random sweep.
+        fn sqrt_inputs() -> Vec<u128> {
+            let mut inputs = vec![0, 1, 2, 3, 4, 5, u128::MAX - 1, u128::MAX];
+            for shift in 0..128 {
+                let power = 1u128 << shift;
+                inputs.extend([power - 1, power, power + 1]);
+            }
+            for root in [2u128, 3, 1_000, u32::MAX as u128, u64::MAX as u128] {
+                let square = root * root;
+                inputs.extend([square - 1, square, square + 1]);
+            }
+            let mut state = 0x9E37_79B9_7F4A_7C15_F39C_C060_5CED_C834u128;
+            for _ in 0..1_000 {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                inputs.push(state >> (state % 128));
+            }
+            inputs
+        }
+
+        #[ink::test]
+        fn isqrt_is_the_exact_floor_root() {
+            for n in sqrt_inputs() {
+                assert_floor_root(n, 1, SimpleContract::isqrt(n));
+            }
+            assert_eq!(SimpleContract::isqrt(u128::MAX), u64::MAX as u128);
+        }
+
+        #[ink::test]
+        fn isqrt_product_is_the_exact_floor_root_past_u128() {
+            let inputs = sqrt_inputs();
+            for (a, b) in inputs.iter().zip(inputs.iter().rev()) {
+                assert_floor_root(*a, *b, SimpleContract::isqrt_product(*a, *b));
+            }
+            assert_eq!(
+                SimpleContract::isqrt_product(u128::MAX, u128::MAX),
+                u128::MAX
+            );
+        }
+
+        #[ink::test]
+        fn mul_div_keeps_the_full_product() {
+            assert_eq!(
+                SimpleContract::mul_div(u128::MAX, u128::MAX, u128::MAX),
+                Ok(u128::MAX)
+            );
+            assert_eq!(SimpleContract::mul_div(u128::MAX, 3, 6), Ok(u128::MAX / 2));
+            assert_eq!(
+                SimpleContract::mul_div_ceil(u128::MAX, 3, 6),
+                Ok(u128::MAX / 2 + 1)
+            );
+            assert_eq!(
+                SimpleContract::mul_div(u128::MAX, 2, 1),
+                Err(Error::Overflow)
+            );
+        }
+
+        #[ink::test]
+        fn adjusted_spot_price_matches_the_market_ratio() {
+            let mut contract = setup();
+            contract.set_decimals(token_a(), 6).unwrap();
+            contract.set_decimals(token_b(), 8).unwrap();
+            // 1000 whole token_a against 2000 whole token_b: one token_a trades for two token_b.
+            add_liquidity(
+                &mut contract,
+                accounts().alice,
+                1_000 * 10u128.pow(6),
+                2_000 * 10u128.pow(8),
+            )
+            .unwrap();
+
+            assert_eq!(
+                contract.get_spot_price_raw(0, token_a(), token_b()),
+                200 * PRECISION
+            );
+            assert_eq!(
+                contract.get_spot_price_adjusted(0, token_a(), token_b()),
+                2 * PRECISION
+            );
+            assert_eq!(
+                contract.get_spot_price_raw(0, token_b(), token_a()),
+                PRECISION / 200
+            );
+            assert_eq!(
+                contract.get_spot_price_adjusted(0, token_b(), token_a()),
+                PRECISION / 2
+            );
+        }
+
+        #[ink::test]
+        fn price_at_returns_the_latest_checkpoint_before_the_block() {
+            let mut contract = setup();
+            let bob = accounts().bob;
+            test::advance_block::<DefaultEnvironment>();
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 1_000_000).unwrap();
+            let price_1 = contract.get_price(0, token_a());
+
+            for _ in 0..4 {
+                test::advance_block::<DefaultEnvironment>();
+            }
+            swap(&mut contract, bob, token_b(), 100_000).unwrap();
+            let price_5 = contract.get_price(0, token_a());
+            for _ in 0..5 {
+                test::advance_block::<DefaultEnvironment>();
+            }
+            swap(&mut contract, bob, token_b(), 100_000).unwrap();
+            let price_10 = contract.get_price(0, token_a());
+            assert!(price_1 < price_5 && price_5 < price_10);
+
+            assert_eq!(contract.get_price_at(0, 0), Err(Error::NoCheckpoint));
+            assert_eq!(contract.get_price_at(0, 1), Ok(price_1));
+            assert_eq!(contract.get_price_at(0, 4), Ok(price_1));
+            assert_eq!(contract.get_price_at(0, 5), Ok(price_5));
+            assert_eq!(contract.get_price_at(0, 9), Ok(price_5));
+            assert_eq!(contract.get_price_at(0, 100), Ok(price_10));
+        }
+
+        #[ink::test]
+        fn prepare_swap_returns_a_usable_minimum() {
+            let mut contract = setup();
+            let bob = accounts().bob;
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 2_000_000).unwrap();
+
+            let (expected_out, min_out) = contract.prepare_swap(0, token_a(), 10_000, 50);
+            assert_eq!(expected_out, contract.quote_swap(0, token_a(), 10_000));
+            assert_eq!(min_out, expected_out * 9_950 / 10_000);
+
+            mock::mint(token_a(), bob, 10_000);
+            set_caller(bob);
+            assert_eq!(
+                contract.swap(0, token_a(), 10_000, min_out, NO_DEADLINE),
+                Ok(expected_out)
+            );
+        }
+
+        #[ink::test]
+        fn get_fees_checked_rejects_tokens_outside_the_pool() {
+            let mut contract = setup();
+            // The protocol keeps the whole fee.
+            contract.set_protocol_fee_bps(10_000).unwrap();
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 1_000_000).unwrap();
+            swap(&mut contract, accounts().bob, token_a(), 100_000).unwrap();
+
+            let fees = contract.get_fees(0, token_a());
+            assert!(fees > 0);
+            assert_eq!(contract.get_fees_checked(0, token_a()), Ok(fees));
+            assert_eq!(contract.get_fees_checked(0, token_b()), Ok(0));
+
+            let unknown = AccountId::from([0xD0; 32]);
+            assert_eq!(
+                contract.get_fees_checked(0, unknown),
+                Err(Error::TokenNotInPool)
+            );
+            assert_eq!(contract.get_fees(0, unknown), 0);
+        }
+
+        #[ink::test]
+        fn total_value_sums_reserves_and_fees_at_spot() {
+            let mut contract = setup();
+            let bob = accounts().bob;
+            contract.set_protocol_fee_bps(5_000).unwrap();
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 3_000_000).unwrap();
+            swap(&mut contract, bob, token_a(), 50_000).unwrap();
+            swap(&mut contract, bob, token_b(), 20_000).unwrap();
+
+            let (reserve_a, reserve_b) = (
+                contract.get_reserve(0, token_a()),
+                contract.get_reserve(0, token_b()),
+            );
+            // Half of each fee goes to the protocol and half to the LPs.
+            let (fees_a, fees_b) = (
+                contract.get_fees(0, token_a()) + contract.get_lp_fees(0, token_a()),
+                contract.get_fees(0, token_b()) + contract.get_lp_fees(0, token_b()),
+            );
+            assert_eq!(
+                contract.get_total_value(0, token_a()),
+                Ok(reserve_a + fees_a + (reserve_b + fees_b) * reserve_a / reserve_b)
+            );
+            assert_eq!(
+                contract.get_total_value(0, token_b()),
+                Ok(reserve_b + fees_b + (reserve_a + fees_a) * reserve_b / reserve_a)
+            );
+            assert_eq!(
+                contract.get_total_value(0, AccountId::from([0xD0; 32])),
+                Err(Error::TokenNotInPool)
+            );
+        }
+
+        #[ink::test]
+        fn total_value_beyond_a_balance_is_an_overflow() {
+            let mut contract = setup();
+            contract.set_reserve(0, token_a(), Balance::MAX / 2);
+            contract.set_reserve(0, token_b(), Balance::MAX / 2);
+            assert_eq!(contract.get_total_value(0, token_a()), Ok(Balance::MAX - 1));
+
+            // Two units of fees push the sum one past `Balance::MAX`.
+            contract.set_fee(0, token_a(), 2);
+            assert_eq!(contract.get_total_value(0, token_a()), Err(Error::Overflow));
+        }
+
+        #[ink::test]
+        fn committed_swap_executes_only_on_a_matching_reveal() {
+            let mut contract = setup();
+            let bob = accounts().bob;
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 1_000_000).unwrap();
+            mock::mint(token_a(), bob, 10_000);
+            set_caller(bob);
+
+            let quote = contract.quote_swap(0, token_a(), 10_000);
+            let mut commitment = <Blake2x256 as ink::env::hash::HashOutput>::Type::default();
+            ink::env::hash_encoded::<Blake2x256, _>(
+                &(0 as PoolId, token_a(), 10_000 as Balance, quote, 7u64),
+                &mut commitment,
+            );
+            assert_eq!(
+                contract.reveal_swap(0, token_a(), 10_000, quote, 7),
+                Err(Error::NoCommitment)
+            );
+            contract.commit_swap(Hash::from(commitment));
+
+            assert_eq!(
+                contract.reveal_swap(0, token_a(), 10_000, quote, 7),
+                Err(Error::RevealTooEarly)
+            );
+            test::advance_block::<DefaultEnvironment>();
+            assert_eq!(
+                contract.reveal_swap(0, token_a(), 10_000, quote, 8),
+                Err(Error::CommitmentMismatch)
+            );
+            assert_eq!(
+                contract.reveal_swap(0, token_a(), 10_000, quote - 1, 7),
+                Err(Error::CommitmentMismatch)
+            );
+
+            assert_eq!(
+                contract.reveal_swap(0, token_a(), 10_000, quote, 7),
+                Ok(quote)
+            );
+            // The commitment is spent.
+            assert_eq!(
+                contract.reveal_swap(0, token_a(), 10_000, quote, 7),
+                Err(Error::NoCommitment)
+            );
+        }
+
+        #[ink::test]
+        fn liquidity_depth_grows_with_the_price_move() {
+            let mut contract = setup();
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 4_000_000).unwrap();
+
+            let levels = vec![
+                5 * PRECISION,
+                4 * PRECISION,
+                3 * PRECISION,
+                2 * PRECISION,
+                PRECISION,
+            ];
+            let depth = contract.get_liquidity_depth(0, token_a(), levels.clone());
+
+            // Levels at or above the current price need nothing; lower ones need ever more.
+            assert_eq!(depth[..2], [0, 0]);
+            assert!(depth.windows(2).skip(1).all(|pair| pair[0] < pair[1]));
+            for (level, amount) in levels.into_iter().zip(depth) {
+                assert_eq!(amount, contract.optimal_arb_amount(0, token_a(), level));
+            }
+        }
+
+        #[ink::test]
+        fn rescue_native_returns_stray_native_tokens() {
+            let mut contract = setup();
+            let bob = accounts().bob;
+            let minimum = ink::env::minimum_balance::<DefaultEnvironment>();
+            test::set_account_balance::<DefaultEnvironment>(
+                AccountId::from(CONTRACT),
+                minimum + 500,
+            );
+            let bob_before = test::get_account_balance::<DefaultEnvironment>(bob).unwrap();
+
+            contract.rescue_native(bob, 500).unwrap();
+
+            assert_eq!(
+                test::get_account_balance::<DefaultEnvironment>(bob),
+                Ok(bob_before + 500)
+            );
+            assert_eq!(
+                test::get_account_balance::<DefaultEnvironment>(AccountId::from(CONTRACT)),
+                Ok(minimum)
+            );
+        }
+
+        #[ink::test]
+        fn rescue_native_keeps_the_existential_deposit() {
+            let mut contract = setup();
+            let minimum = ink::env::minimum_balance::<DefaultEnvironment>();
+            test::set_account_balance::<DefaultEnvironment>(
+                AccountId::from(CONTRACT),
+                minimum + 500,
+            );
+
+            assert_eq!(
+                contract.rescue_native(accounts().bob, 501),
+                Err(Error::InsufficientNativeBalance { free_balance: 500 })
+            );
+
+            set_caller(accounts().bob);
+            assert_eq!(
+                contract.rescue_native(accounts().bob, 1),
+                Err(Error::NotOwner)
+            );
+        }
+
+        #[ink::test]
+        fn register_tokens_batch_registers_every_entry() {
+            let mut contract = setup();
+            let token_c = AccountId::from([0xD0; 32]);
+
+            contract
+                .register_tokens_batch(vec![(token_a(), 6), (token_b(), 18), (token_c, 36)])
+                .unwrap();
+
+            assert_eq!(contract.get_decimals(token_a()), 6);
+            assert_eq!(contract.get_decimals(token_b()), 18);
+            assert_eq!(contract.get_decimals(token_c), 36);
+        }
+
+        #[ink::test]
+        fn set_decimals_rejects_implausible_decimals_and_non_owners() {
+            let mut contract = setup();
+            assert_eq!(
+                contract.set_decimals(token_a(), MAX_DECIMALS + 1),
+                Err(Error::InvalidDecimals { token: token_a() })
+            );
+            assert_eq!(contract.get_decimals(token_a()), 0);
+
+            set_caller(accounts().bob);
+            assert_eq!(contract.set_decimals(token_a(), 6), Err(Error::NotOwner));
+            assert_eq!(contract.get_decimals(token_a()), 0);
+        }
+
+        #[ink::test]
+        fn register_tokens_batch_rejects_the_whole_batch() {
+            let mut contract = setup();
+
+            assert_eq!(
+                contract.register_tokens_batch(vec![(token_a(), 6), (token_b(), 37)]),
+                Err(Error::InvalidDecimals { token: token_b() })
+            );
+            assert_eq!(contract.get_decimals(token_a()), 0);
+            assert_eq!(contract.get_decimals(token_b()), 0);
+
+            set_caller(accounts().bob);
+            assert_eq!(
+                contract.register_tokens_batch(vec![(token_a(), 6)]),
+                Err(Error::NotOwner)
+            );
+        }
+
+        #[ink::test]
+        fn effective_reserves_include_the_fees() {
+            let mut contract = setup();
+            let bob = accounts().bob;
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 1_000_000).unwrap();
+            swap(&mut contract, bob, token_a(), 100_000).unwrap();
+            swap(&mut contract, bob, token_b(), 100_000).unwrap();
+
+            let (reserve_a, reserve_b) = (
+                contract.get_reserve(0, token_a()),
+                contract.get_reserve(0, token_b()),
+            );
+            let (effective_a, effective_b) = contract.get_effective_reserves(0).unwrap();
+            assert!(effective_a > reserve_a && effective_b > reserve_b);
+            assert_eq!(effective_a, reserve_a + contract.get_lp_fees(0, token_a()));
+            assert_eq!(effective_b, reserve_b + contract.get_lp_fees(0, token_b()));
+        }
+
+        #[ink::test]
+        fn effective_reserves_beyond_a_balance_are_an_overflow() {
+            let mut contract = setup();
+            contract.set_reserve(0, token_b(), Balance::MAX);
+            assert_eq!(contract.get_effective_reserves(0), Ok((0, Balance::MAX)));
+
+            contract.set_lp_fee(0, token_b(), 1);
+            assert_eq!(contract.get_effective_reserves(0), Err(Error::Overflow));
+            assert_eq!(contract.get_effective_reserves(1), Err(Error::PoolNotFound));
+        }
+
+        #[ink::test]
+        fn swap_rate_limit_applies_per_block() {
+            let mut contract = setup();
+            let bob = accounts().bob;
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 1_000_000).unwrap();
+            set_caller(accounts().alice);
+            contract.set_swap_rate_limit(2).unwrap();
+
+            assert!(swap(&mut contract, bob, token_a(), 1_000).is_ok());
+            assert!(swap(&mut contract, bob, token_b(), 1_000).is_ok());
+            assert_eq!(
+                swap(&mut contract, bob, token_a(), 1_000),
+                Err(Error::RateLimited)
+            );
+            // Other accounts have their own allowance.
+            assert!(swap(&mut contract, accounts().charlie, token_a(), 1_000).is_ok());
+
+            test::advance_block::<DefaultEnvironment>();
+            assert!(swap(&mut contract, bob, token_a(), 1_000).is_ok());
+            assert_eq!(contract.get_account_swap_count(bob), 3);
+        }
+
+        #[ink::test]
+        fn swap_route_counts_once_against_the_rate_limit() {
+            let mut contract = setup();
+            let (alice, bob) = (accounts().alice, accounts().bob);
+            let token_c = AccountId::from([0xD0; 32]);
+            add_liquidity(&mut contract, alice, 1_000_000, 1_000_000).unwrap();
+            let pool_id = contract.create_pool(token_b(), token_c, FEE_BPS).unwrap();
+            mock::mint(token_b(), alice, 1_000_000);
+            mock::mint(token_c, alice, 1_000_000);
+            contract
+                .add_liquidity(pool_id, 1_000_000, 1_000_000, NO_DEADLINE)
+                .unwrap();
+            contract.set_swap_rate_limit(1).unwrap();
+
+            mock::mint(token_a(), bob, 1_000);
+            set_caller(bob);
+            assert!(contract
+                .swap_route(vec![token_a(), token_b(), token_c], 1_000, 0, Vec::new())
+                .is_ok());
+            assert_eq!(contract.get_account_swap_count(bob), 1);
+            assert_eq!(
+                swap(&mut contract, bob, token_a(), 1_000),
+                Err(Error::RateLimited)
+            );
+        }
+
+        #[cfg(feature = "std")]
+        #[ink::test]
+        fn summary_shows_reserves_fees_and_price() {
+            let mut contract = setup();
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 2_000_000).unwrap();
+            swap(&mut contract, accounts().bob, token_a(), 100_000).unwrap();
+
+            let summary = contract.summary(0);
+            assert!(summary.starts_with("pool 0 "));
+            assert!(summary.contains(&format!(
+                "reserves {}/{}, fees {}/0, price {}",
+                contract.get_reserve(0, token_a()),
+                contract.get_reserve(0, token_b()),
+                contract.get_fees(0, token_a()),
+                contract.get_price(0, token_a()),
+            )));
+        }
+
+        #[ink::test]
+        fn swap_split_matches_a_single_swap() {
+            let mut contract = setup();
+            let bob = accounts().bob;
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 1_000_000).unwrap();
+            mock::mint(token_a(), bob, 400_000);
+            set_caller(bob);
+
+            let single = contract.quote_swap(0, token_a(), 400_000);
+            assert_eq!(
+                contract.swap_split(0, token_a(), 400_000, 0, 0),
+                Err(Error::InvalidChunks)
+            );
+            // Splitting two units into three chunks would leave a part empty.
+            assert_eq!(
+                contract.swap_split(0, token_a(), 2, 3, 0),
+                Err(Error::InvalidChunks)
+            );
+            assert_eq!(contract.get_account_swap_count(bob), 0);
+            let split = contract.swap_split(0, token_a(), 400_000, 4, 0).unwrap();
+
+            // Only the per-part rounding of the fee and the output tells the two apart.
+            assert!(split <= single && single - split <= 4);
+            assert_eq!(contract.get_account_swap_count(bob), 4);
+        }
+
+        #[ink::test]
+        fn bootstrap_deposit_needs_both_tokens() {
+            test::set_callee::<DefaultEnvironment>(AccountId::from(CONTRACT));
+            set_caller(accounts().alice);
+            let mut contract =
+                SimpleContract::new_with_minimum_liquidity(token_a(), token_b(), FEE_BPS, 10)
+                    .unwrap();
+            let alice = accounts().alice;
+
+            assert_eq!(
+                add_liquidity(&mut contract, alice, 0, 100),
+                Err(Error::InvalidInitialRatio)
+            );
+            assert_eq!(
+                add_liquidity(&mut contract, alice, 100, 0),
+                Err(Error::InvalidInitialRatio)
+            );
+            // sqrt(50 * 100) = 70, of which 10 are locked.
+            assert_eq!(add_liquidity(&mut contract, alice, 50, 100), Ok(60));
+        }
+
+        #[ink::test]
+        fn remove_liquidity_returns_a_proportional_share() {
+            let mut contract = setup();
+            let (alice, bob) = (accounts().alice, accounts().bob);
+            add_liquidity(&mut contract, alice, 1_000_000, 4_000_000).unwrap();
+            let liquidity = add_liquidity(&mut contract, bob, 100_000, 400_000).unwrap();
+
+            set_caller(accounts().eve);
+            assert_eq!(contract.remove_liquidity(0, 1), Err(Error::NoLiquidity));
+            set_caller(bob);
+            assert_eq!(
+                contract.remove_liquidity(0, liquidity + 1),
+                Err(Error::InsufficientBalance)
+            );
+
+            assert_eq!(
+                contract.remove_liquidity(0, liquidity / 2),
+                Ok((50_000, 200_000))
+            );
+            assert!(matches!(
+                emitted_events().last(),
+                Some(Event::LiquidityRemoved(LiquidityRemoved { amounts: (50_000, 200_000), account, .. }))
+                    if *account == bob
+            ));
+            assert_eq!(contract.lp_balance_of(bob, 0), liquidity - liquidity / 2);
+            assert_eq!(contract.get_reserve(0, token_a()), 1_050_000);
+            assert_eq!(contract.get_reserve(0, token_b()), 4_200_000);
+            assert_eq!(
+                (mock::balance(token_a(), bob), mock::balance(token_b(), bob)),
+                (50_000, 200_000)
+            );
+
+            // Withdrawing a single LP token still pays out its rounded-down share.
+            assert_eq!(contract.remove_liquidity(0, 1), Ok((0, 2)));
+        }
+
+        #[ink::test]
+        fn counterpart_routes_both_tokens_and_rejects_others() {
+            let contract = setup();
+
+            assert_eq!(contract.counterpart(0, token_a()), Ok(token_b()));
+            assert_eq!(contract.counterpart(0, token_b()), Ok(token_a()));
+            assert_eq!(
+                contract.counterpart(0, AccountId::from([0xD0; 32])),
+                Err(Error::TokenNotInPool)
+            );
+        }
+
+        #[ink::test]
+        fn swap_fee_is_split_between_the_protocol_and_lps() {
+            let mut contract = setup();
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 1_000_000).unwrap();
+
+            // By default the LPs earn the whole fee.
+            swap(&mut contract, accounts().bob, token_a(), 100_000).unwrap();
+            assert_eq!(contract.get_lp_fees(0, token_a()), 100_000 * 3 / 1000);
+            assert_eq!(contract.get_fees(0, token_a()), 0);
+            // Only the input net of the fee enters the reserve.
+            assert_eq!(contract.get_reserve(0, token_a()), 1_000_000 + 99_700);
+
+            set_caller(accounts().alice);
+            assert_eq!(
+                contract.set_protocol_fee_bps(10_001),
+                Err(Error::InvalidFee)
+            );
+            contract.set_protocol_fee_bps(2_000).unwrap();
+            assert_eq!(contract.get_protocol_fee_bps(), 2_000);
+            swap(&mut contract, accounts().bob, token_a(), 100_000).unwrap();
+            assert_eq!(contract.get_fees(0, token_a()), 60);
+            assert_eq!(contract.get_lp_fees(0, token_a()), 300 + 240);
+
+            set_caller(accounts().bob);
+            assert_eq!(contract.set_protocol_fee_bps(0), Err(Error::NotOwner));
+        }
+
+        #[ink::test]
+        fn failed_swaps_and_deposits_return_errors_without_events() {
+            let mut contract = setup();
+            let bob = accounts().bob;
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 1_000_000).unwrap();
+            let event_count = emitted_events().len();
+
+            assert_eq!(
+                swap(&mut contract, bob, AccountId::from([0xD0; 32]), 1_000),
+                Err(Error::TokenNotInPool)
+            );
+            set_caller(bob);
+            assert_eq!(
+                contract.swap_for_exact_out(0, token_b(), 1_000_000, Balance::MAX),
+                Err(Error::InsufficientReserve)
+            );
+            assert_eq!(
+                contract.add_liquidity(1, 1_000, 1_000, NO_DEADLINE),
+                Err(Error::PoolNotFound)
+            );
+            assert_eq!(emitted_events().len(), event_count);
+        }
+
+        #[ink::test]
+        fn marginal_prices_straddle_the_mid_price_by_the_fee() {
+            let mut contract = setup();
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 4_000_000).unwrap();
+            let mid_0 = contract.get_price(0, token_a());
+            let mid_1 = contract.get_price(0, token_b());
+
+            let (price_0, price_1) = contract.get_marginal_prices(0);
+            assert_eq!(price_0, mid_0 * 10_000 / 9_970);
+            assert_eq!(price_1, mid_1 * 10_000 / 9_970);
+            // Buying and selling back loses the fee twice.
+            let round_trip = price_0 * price_1 / PRECISION;
+            assert!(round_trip > PRECISION);
+            assert!(round_trip.abs_diff(PRECISION * 10_000 * 10_000 / (9_970 * 9_970)) <= 10);
+        }
+
+        #[ink::test]
+        fn marginal_prices_saturate_instead_of_overflowing() {
+            let mut contract = setup();
+            contract.set_reserve(0, token_a(), 1);
+            contract.set_reserve(0, token_b(), Balance::MAX);
+
+            let (price_0, price_1) = contract.get_marginal_prices(0);
+            assert_eq!(price_0, Balance::MAX);
+            assert_eq!(price_1, 0);
+        }
+
+        #[ink::test]
+        fn swap_one_above_the_quote_is_rejected() {
+            let mut contract = setup();
+            let bob = accounts().bob;
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 1_000_000).unwrap();
+            mock::mint(token_a(), bob, 10_000);
+            set_caller(bob);
+            let quote = contract.quote_swap(0, token_a(), 10_000);
+
+            assert_eq!(
+                contract.swap(0, token_a(), 10_000, quote + 1, NO_DEADLINE),
+                Err(Error::SlippageExceeded)
+            );
+            assert_eq!(contract.get_reserve(0, token_a()), 1_000_000);
+            assert_eq!(contract.get_reserve(0, token_b()), 1_000_000);
+
+            assert_eq!(
+                contract.swap(0, token_a(), 10_000, quote, NO_DEADLINE),
+                Ok(quote)
+            );
+        }
+
+        #[ink::test]
+        fn lp_tokens_mint_and_burn_at_18_decimal_scale() {
+            let mut contract = setup();
+            let (alice, bob) = (accounts().alice, accounts().bob);
+            let unit = 10u128.pow(18);
+
+            // The first mint is sqrt(10^24 * 4 * 10^24), past what a u128 product can hold.
+            let alice_liquidity =
+                add_liquidity(&mut contract, alice, 1_000_000 * unit, 4_000_000 * unit).unwrap();
+            assert_eq!(alice_liquidity, 2_000_000 * unit - MINIMUM_LIQUIDITY);
+            assert_eq!(contract.total_supply(0), 2_000_000 * unit);
+
+            let bob_liquidity =
+                add_liquidity(&mut contract, bob, 100_000 * unit, 400_000 * unit).unwrap();
+            assert_eq!(bob_liquidity, 200_000 * unit);
+            assert_eq!(contract.total_supply(0), 2_200_000 * unit);
+
+            set_caller(bob);
+            assert_eq!(
+                contract.remove_liquidity(0, bob_liquidity),
+                Ok((100_000 * unit, 400_000 * unit))
+            );
+            assert_eq!(contract.total_supply(0), 2_000_000 * unit);
+        }
+
+        #[ink::test]
+        fn quote_matches_swap_from_dust_to_near_draining() {
+            let mut contract = setup();
+            let bob = accounts().bob;
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 1_000_000).unwrap();
+
+            // Dust, amounts either side of the fee's rounding boundaries, and inputs large
+            // enough to all but drain the other reserve, alternating direction.
+            let amounts = [
+                1,
+                2,
+                3,
+                4,
+                333,
+                334,
+                335,
+                1_000,
+                1_003,
+                9_999,
+                10_001,
+                123_457,
+                1_000_000,
+                99_999_999,
+                10u128.pow(12),
+            ];
+            for (i, amount) in amounts.into_iter().enumerate() {
+                let token_in = if i % 2 == 0 { token_a() } else { token_b() };
+                let quote = contract.quote_swap(0, token_in, amount);
+                let result = swap(&mut contract, bob, token_in, amount);
+                if quote == 0 {
+                    assert!(result.is_err(), "amount {amount}");
+                } else {
+                    assert_eq!(result, Ok(quote), "amount {amount}");
+                }
+            }
+        }
+
+        #[ink::test]
+        fn near_max_reserves_fail_gracefully() {
+            let mut contract = setup();
+            contract.set_reserve(0, token_a(), Balance::MAX - 10);
+            contract.set_reserve(0, token_b(), Balance::MAX - 10);
+
+            assert_eq!(
+                swap(&mut contract, accounts().bob, token_a(), 1_000_000),
+                Err(Error::Overflow)
+            );
+            // The quote reports the same error rather than panicking.
+            assert_eq!(
+                contract.get_amount_out(0, token_a(), 1_000_000),
+                Err(Error::Overflow)
+            );
+            // So does an input too large to take the fee from.
+            assert_eq!(
+                swap(&mut contract, accounts().charlie, token_a(), Balance::MAX),
+                Err(Error::Overflow)
+            );
+        }
+
+        #[ink::test]
+        fn single_token_exit_equals_dual_exit_plus_swap() {
+            let mut contract = setup();
+            let (alice, bob) = (accounts().alice, accounts().bob);
+            let token_c = AccountId::from([0xD0; 32]);
+            // Pool 1 mirrors pool 0 with token_c in place of token_b.
+            contract.create_pool(token_a(), token_c, FEE_BPS).unwrap();
+            for (pool_id, other) in [(0, token_b()), (1, token_c)] {
+                for account in [alice, bob] {
+                    mock::mint(token_a(), account, 1_000_000);
+                    mock::mint(other, account, 3_000_000);
+                    set_caller(account);
+                    contract
+                        .add_liquidity(pool_id, 1_000_000, 3_000_000, NO_DEADLINE)
+                        .unwrap();
+                }
+            }
+            let liquidity = contract.lp_balance_of(bob, 0);
+            let (held_a, held_c) = (mock::balance(token_a(), bob), mock::balance(token_c, bob));
+
+            set_caller(bob);
+            let single = contract
+                .remove_liquidity_single(0, liquidity, token_a(), 0)
+                .unwrap();
+            let (direct, other_amount) = contract.remove_liquidity(1, liquidity).unwrap();
+            let swapped = contract
+                .swap(1, token_c, other_amount, 0, NO_DEADLINE)
+                .unwrap();
+
+            assert_eq!(single, direct + swapped);
+            assert_eq!(mock::balance(token_a(), bob), held_a + 2 * single);
+            assert_eq!(mock::balance(token_c, bob), held_c);
+            // Both exits leave the pools in the same state and count one swap each.
+            assert_eq!(
+                contract.get_reserve(0, token_a()),
+                contract.get_reserve(1, token_a())
+            );
+            assert_eq!(
+                contract.get_reserve(0, token_b()),
+                contract.get_reserve(1, token_c)
+            );
+            assert_eq!(
+                contract.get_fees(0, token_b()),
+                contract.get_fees(1, token_c)
+            );
+            assert_eq!(contract.get_account_swap_count(bob), 2);
+        }
+
+        #[ink::test]
+        fn single_token_exit_enforces_the_minimum() {
+            let mut contract = setup();
+            let bob = accounts().bob;
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 1_000_000).unwrap();
+            let liquidity = add_liquidity(&mut contract, bob, 100_000, 100_000).unwrap();
+
+            set_caller(bob);
+            assert_eq!(
+                contract.remove_liquidity_single(0, liquidity, token_a(), 200_000),
+                Err(Error::SlippageExceeded)
+            );
+        }
+
+        #[ink::test]
+        fn unequal_deposit_uses_the_limiting_token() {
+            let mut contract = setup();
+            let bob = accounts().bob;
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 2_000_000).unwrap();
+
+            // token_b limits the deposit, so only half of it in token_a is taken.
+            add_liquidity(&mut contract, bob, 300_000, 400_000).unwrap();
+            assert!(matches!(
+                emitted_events().last(),
+                Some(Event::LiquidityAdded(LiquidityAdded {
+                    amounts: (200_000, 400_000),
+                    ..
+                }))
+            ));
+            assert_eq!(mock::balance(token_a(), bob), 100_000);
+            assert_eq!(mock::balance(token_b(), bob), 0);
+
+            // token_a limits it the other way round.
+            add_liquidity(&mut contract, bob, 100_000, 500_000).unwrap();
+            assert!(matches!(
+                emitted_events().last(),
+                Some(Event::LiquidityAdded(LiquidityAdded {
+                    amounts: (100_000, 200_000),
+                    ..
+                }))
+            ));
+            assert_eq!(contract.get_reserve(0, token_a()), 1_300_000);
+            assert_eq!(contract.get_reserve(0, token_b()), 2_600_000);
+        }
+
+        #[ink::test]
+        fn get_amount_out_quotes_without_writing() {
+            let mut contract = setup();
+            let unknown = AccountId::from([0xD0; 32]);
+            assert_eq!(contract.get_amount_out(0, token_a(), 1_000), Ok(0));
+            assert_eq!(
+                contract.get_amount_out(0, unknown, 1_000),
+                Err(Error::TokenNotInPool)
+            );
+
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 1_000_000).unwrap();
+            let event_count = emitted_events().len();
+            let quote = contract.get_amount_out(0, token_a(), 1_000).unwrap();
+            assert_eq!(emitted_events().len(), event_count);
+            assert_eq!(contract.get_reserve(0, token_a()), 1_000_000);
+            assert_eq!(
+                swap(&mut contract, accounts().bob, token_a(), 1_000),
+                Ok(quote)
+            );
+        }
+
+        #[ink::test]
+        fn pools_are_registered_once_per_pair_and_kept_apart() {
+            let mut contract = setup();
+            let alice = accounts().alice;
+            let token_c = AccountId::from([0xD0; 32]);
+
+            assert_eq!(
+                contract.create_pool(token_b(), token_a(), FEE_BPS),
+                Err(Error::PoolExists { pool_id: 0 })
+            );
+            assert_eq!(contract.create_pool(token_c, token_a(), FEE_BPS), Ok(1));
+            assert_eq!(contract.get_pool_id(token_a(), token_c), Some(1));
+            assert_eq!(contract.get_pool_id(token_c, token_a()), Some(1));
+            assert_eq!(contract.get_pool_id(token_b(), token_c), None);
+            assert_eq!(contract.get_pool_count(), 2);
+
+            add_liquidity(&mut contract, alice, 1_000_000, 2_000_000).unwrap();
+            mock::mint(token_a(), alice, 5_000);
+            mock::mint(token_c, alice, 7_000);
+            contract
+                .add_liquidity(1, 5_000, 7_000, NO_DEADLINE)
+                .unwrap();
+
+            assert_eq!(contract.get_reserve(0, token_a()), 1_000_000);
+            assert_eq!(contract.get_reserve(1, token_a()), 5_000);
+            assert_eq!(contract.get_reserve(1, token_c), 7_000);
+            assert_eq!(contract.balance_of(alice, 1, token_a()), 5_000);
+            assert_eq!(contract.balance_of(alice, 0, token_a()), 1_000_000);
+        }
+
+        #[ink::test]
+        fn low_fee_pool_charges_its_own_rate() {
+            test::set_callee::<DefaultEnvironment>(AccountId::from(CONTRACT));
+            set_caller(accounts().alice);
+            assert!(matches!(
+                SimpleContract::new(token_a(), token_b(), 10_000),
+                Err(Error::InvalidFee)
+            ));
+            let mut contract = SimpleContract::new(token_a(), token_b(), 5).unwrap();
+            assert_eq!(contract.get_fee_bps(0), 5);
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 1_000_000).unwrap();
+
+            // 5 bps of 100_000 is 50, leaving 99_950 to trade.
+            let amount_out = swap(&mut contract, accounts().bob, token_a(), 100_000).unwrap();
+            assert_eq!(amount_out, 99_950 * 1_000_000 / (1_000_000 + 99_950));
+            assert_eq!(contract.get_lp_fees(0, token_a()), 50);
+        }
+
+        #[ink::test]
+        fn remove_liquidity_to_pays_another_account() {
+            let mut contract = setup();
+            let (alice, bob) = (accounts().alice, accounts().bob);
+            let liquidity = add_liquidity(&mut contract, alice, 1_000_000, 1_000_000).unwrap();
+
+            set_caller(alice);
+            let amounts = contract.remove_liquidity_to(0, liquidity / 2, bob).unwrap();
+
+            assert_eq!(contract.lp_balance_of(alice, 0), liquidity - liquidity / 2);
+            assert_eq!(contract.lp_balance_of(bob, 0), 0);
+            assert_eq!(
+                (mock::balance(token_a(), bob), mock::balance(token_b(), bob)),
+                amounts
+            );
+            assert_eq!(
+                (
+                    mock::balance(token_a(), alice),
+                    mock::balance(token_b(), alice)
+                ),
+                (0, 0)
+            );
+            assert!(matches!(
+                emitted_events().last(),
+                Some(Event::LiquidityRemoved(LiquidityRemoved { account, recipient, .. }))
+                    if *account == alice && *recipient == bob
+            ));
+        }
+
+        #[ink::test]
+        fn swaps_leave_the_deposit_ledger_alone() {
+            let mut contract = setup();
+            let (alice, bob) = (accounts().alice, accounts().bob);
+            add_liquidity(&mut contract, alice, 1_000_000, 1_000_000).unwrap();
+
+            // Bob holds no position, so there is nothing of his to debit.
+            assert!(swap(&mut contract, bob, token_a(), 50_000).is_ok());
+            assert!(swap(&mut contract, bob, token_b(), 80_000).is_ok());
+            // Swapping does not turn a trade into a deposit, for traders or LPs.
+            assert!(swap(&mut contract, alice, token_b(), 10_000).is_ok());
+
+            for token in [token_a(), token_b()] {
+                assert_eq!(contract.balance_of(bob, 0, token), 0);
+                assert_eq!(contract.balance_of(alice, 0, token), 1_000_000);
+            }
+        }
+
+        #[ink::test]
+        fn invariant_report_flags_each_corruption() {
+            let mut contract = setup();
+            let (alice, bob) = (accounts().alice, accounts().bob);
+            add_liquidity(&mut contract, alice, 1_000_000, 1_000_000).unwrap();
+            add_liquidity(&mut contract, bob, 300_000, 300_000).unwrap();
+            swap(&mut contract, bob, token_a(), 100_000).unwrap();
+            let healthy = InvariantReport {
+                k_consistent: true,
+                shares_consistent: true,
+                reserves_backed: true,
+                no_negative_implied: true,
+            };
+            assert_eq!(contract.invariant_report(0), healthy);
+
+            // A supply minted from nothing outgrows sqrt(k) and the balances.
+            let total_supply = contract.total_supply(0);
+            contract.total_lp_supply.insert(0, &(2 * total_supply));
+            assert_eq!(
+                contract.invariant_report(0),
+                InvariantReport {
+                    k_consistent: false,
+                    shares_consistent: false,
+                    ..healthy
+                }
+            );
+            contract.total_lp_supply.insert(0, &total_supply);
+
+            // A reserve the contract does not hold.
+            let reserve = contract.get_reserve(0, token_b());
+            contract.set_reserve(0, token_b(), reserve + mock::balance(token_b(), alice) + 1);
+            assert_eq!(
+                contract.invariant_report(0),
+                InvariantReport {
+                    reserves_backed: false,
+                    ..healthy
+                }
+            );
+            contract.set_reserve(0, token_b(), reserve);
+
+            // Fees beyond everything ever accrued.
+            let accrued = contract.fees_accrued.get((0, token_a())).unwrap();
+            contract.fees_accrued.insert((0, token_a()), &(accrued - 1));
+            assert_eq!(
+                contract.invariant_report(0),
+                InvariantReport {
+                    no_negative_implied: false,
+                    ..healthy
+                }
+            );
+        }
+
+        #[ink::test]
+        fn expired_deadlines_are_rejected() {
+            let mut contract = setup();
+            let bob = accounts().bob;
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 1_000_000).unwrap();
+            for _ in 0..5 {
+                test::advance_block::<DefaultEnvironment>();
+            }
+            mock::mint(token_a(), bob, 20_000);
+            mock::mint(token_b(), bob, 10_000);
+            set_caller(bob);
+
+            assert_eq!(
+                contract.swap(0, token_a(), 10_000, 0, 4),
+                Err(Error::Expired)
+            );
+            assert_eq!(
+                contract.add_liquidity(0, 10_000, 10_000, 4),
+                Err(Error::Expired)
+            );
+            // The deadline block itself is still in time.
+            assert!(contract.swap(0, token_a(), 10_000, 0, 5).is_ok());
+            assert!(contract.add_liquidity(0, 10_000, 10_000, 5).is_ok());
+        }
+
+        #[ink::test]
+        fn pool_operations_settle_in_psp22_tokens() {
+            let mut contract = setup();
+            let (alice, bob) = (accounts().alice, accounts().bob);
+            let holdings = || {
+                (
+                    mock::balance(token_a(), AccountId::from(CONTRACT)),
+                    mock::balance(token_b(), AccountId::from(CONTRACT)),
+                )
+            };
+
+            let liquidity = add_liquidity(&mut contract, alice, 1_000_000, 1_000_000).unwrap();
+            assert_eq!(holdings(), (1_000_000, 1_000_000));
+            assert_eq!(mock::balance(token_a(), alice), 0);
+
+            let amount_out = swap(&mut contract, bob, token_a(), 10_000).unwrap();
+            assert_eq!(holdings(), (1_010_000, 1_000_000 - amount_out));
+            assert_eq!(mock::balance(token_b(), bob), amount_out);
+
+            set_caller(alice);
+            let (amount_a, amount_b) = contract.remove_liquidity(0, liquidity).unwrap();
+            assert_eq!(
+                (
+                    mock::balance(token_a(), alice),
+                    mock::balance(token_b(), alice)
+                ),
+                (amount_a, amount_b)
+            );
+
+            // A transfer the caller cannot fund fails the call.
+            set_caller(bob);
+            assert_eq!(
+                contract.swap(0, token_a(), 10_000, 0, NO_DEADLINE),
+                Err(Error::TransferFailed)
+            );
+        }
+
+        #[ink::test]
+        fn skim_recovers_fees_zeroed_by_reset() {
+            let mut contract = setup();
+            // The protocol keeps the whole fee.
+            contract.set_protocol_fee_bps(10_000).unwrap();
+            let (alice, eve) = (accounts().alice, accounts().eve);
+            add_liquidity(&mut contract, alice, 1_000_000, 1_000_000).unwrap();
+            swap(&mut contract, accounts().bob, token_a(), 100_000).unwrap();
+            let fees = contract.get_fees(0, token_a());
+
+            set_caller(alice);
+            assert_eq!(contract.skim(token_a(), eve), Ok(0));
+            contract.reset_fees(0, token_a()).unwrap();
+            assert_eq!(contract.skim(token_a(), eve), Ok(fees));
+
+            assert_eq!(mock::balance(token_a(), eve), fees);
+            assert!(matches!(
+                emitted_events().last(),
+                Some(Event::Skimmed(Skimmed { amount, recipient, .. }))
+                    if *amount == fees && *recipient == eve
+            ));
+            assert!(contract.invariant_report(0).reserves_backed);
+            set_caller(eve);
+            assert_eq!(contract.skim(token_a(), eve), Err(Error::NotOwner));
+        }
+
+        #[ink::test]
+        fn fee_ratio_grows_as_fees_accrue() {
+            let mut contract = setup();
+            let bob = accounts().bob;
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 1_000_000).unwrap();
+            assert_eq!(contract.get_fee_ratio_bps(0, token_a()), 0);
+
+            swap(&mut contract, bob, token_a(), 100_000).unwrap();
+            let first = contract.get_fee_ratio_bps(0, token_a());
+            let (fees, reserve) = (
+                contract.get_lp_fees(0, token_a()),
+                contract.get_reserve(0, token_a()),
+            );
+            assert_eq!(first as Balance, fees * 10_000 / (reserve + fees));
+
+            swap(&mut contract, bob, token_a(), 100_000).unwrap();
+            assert!(contract.get_fee_ratio_bps(0, token_a()) > first);
+            assert_eq!(contract.get_fee_ratio_bps(0, token_b()), 0);
+        }
+
+        #[ink::test]
+        fn withdraw_fees_is_owner_only_and_pays_the_recipient() {
+            let mut contract = setup();
+            // The protocol keeps the whole fee.
+            contract.set_protocol_fee_bps(10_000).unwrap();
+            let (alice, bob, eve) = (accounts().alice, accounts().bob, accounts().eve);
+            add_liquidity(&mut contract, alice, 1_000_000, 1_000_000).unwrap();
+            swap(&mut contract, bob, token_a(), 100_000).unwrap();
+            let fees = contract.get_fees(0, token_a());
+
+            set_caller(bob);
+            assert_eq!(
+                contract.withdraw_fees(0, token_a(), bob),
+                Err(Error::NotOwner)
+            );
+            assert_eq!(contract.withdraw_all_fees(0), Err(Error::NotOwner));
+            assert_eq!(contract.get_fees(0, token_a()), fees);
+
+            set_caller(alice);
+            assert_eq!(contract.withdraw_fees(0, token_a(), eve), Ok(fees));
+            assert_eq!(contract.get_fees(0, token_a()), 0);
+            assert_eq!(mock::balance(token_a(), eve), fees);
+            assert!(matches!(
+                emitted_events().last(),
+                Some(Event::FeesWithdrawn(FeesWithdrawn { amount, recipient, .. }))
+                    if *amount == fees && *recipient == eve
+            ));
+        }
+
+        #[ink::test]
+        fn pause_reason_is_recorded_and_announced() {
+            let mut contract = setup();
+            let eve = accounts().eve;
+            contract.set_guardian(eve).unwrap();
+            assert_eq!(contract.get_pause_reason(), 0);
+
+            set_caller(eve);
+            contract.emergency_pause(42).unwrap();
+
+            assert_eq!(contract.get_pause_reason(), 42);
+            assert!(matches!(
+                emitted_events().last(),
+                Some(Event::Paused(Paused { reason: 42 }))
+            ));
+        }
+
+        #[ink::test]
+        fn operational_pause_halts_trading_but_not_exits() {
+            let mut contract = setup();
+            let (alice, bob) = (accounts().alice, accounts().bob);
+            let liquidity = add_liquidity(&mut contract, alice, 1_000_000, 1_000_000).unwrap();
+
+            set_caller(alice);
+            contract.set_paused(true).unwrap();
+            assert!(contract.is_paused());
+            assert_eq!(
+                swap(&mut contract, bob, token_a(), 10_000),
+                Err(Error::Paused)
+            );
+            assert_eq!(
+                add_liquidity(&mut contract, bob, 10_000, 10_000),
+                Err(Error::Paused)
+            );
+            set_caller(alice);
+            assert!(contract.remove_liquidity(0, liquidity / 2).is_ok());
+
+            contract.set_paused(false).unwrap();
+            assert!(!contract.is_paused());
+            assert!(swap(&mut contract, bob, token_a(), 10_000).is_ok());
+        }
+
+        #[ink::test]
+        fn operational_pause_is_owner_only() {
+            let mut contract = setup();
+            set_caller(accounts().bob);
+            assert_eq!(contract.set_paused(true), Err(Error::NotOwner));
+            assert!(!contract.is_paused());
+            assert_eq!(contract.set_swap_rate_limit(1), Err(Error::NotOwner));
+            assert_eq!(contract.get_config(0).swap_rate_limit, 0);
+        }
+
+        #[ink::test]
+        fn exact_out_swap_charges_the_least_sufficient_input() {
+            let mut contract = setup();
+            let bob = accounts().bob;
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 2_000_000).unwrap();
+            mock::mint(token_a(), bob, 100_000);
+            set_caller(bob);
+
+            let amount_in = contract
+                .swap_for_exact_out(0, token_b(), 50_000, 100_000)
+                .unwrap();
+            let received = mock::balance(token_b(), bob);
+            assert!(received >= 50_000);
+            assert_eq!(mock::balance(token_a(), bob), 100_000 - amount_in);
+
+            // The fee is taken from the input, as in `swap`.
+            assert_eq!(
+                contract.get_lp_fees(0, token_a()),
+                amount_in - amount_in * 9_970 / 10_000
+            );
+            // Back at the original reserves, one unit less would not have bought the output.
+            contract.set_reserve(0, token_a(), 1_000_000);
+            contract.set_reserve(0, token_b(), 2_000_000);
+            assert!(contract.quote_swap(0, token_a(), amount_in - 1) < 50_000);
+            assert_eq!(contract.quote_swap(0, token_a(), amount_in), received);
+
+            assert_eq!(
+                contract.swap_for_exact_out(0, token_b(), 50_000, amount_in - 1),
+                Err(Error::ExcessiveInput)
+            );
+        }
+
+        #[ink::test]
+        fn account_summary_reports_every_field() {
+            let mut contract = setup();
+            let (alice, bob) = (accounts().alice, accounts().bob);
+            add_liquidity(&mut contract, alice, 1_000_000, 1_000_000).unwrap();
+            let liquidity = add_liquidity(&mut contract, bob, 250_000, 250_000).unwrap();
+            swap(&mut contract, bob, token_a(), 10_000).unwrap();
+            swap(&mut contract, bob, token_b(), 5_000).unwrap();
+            swap(&mut contract, alice, token_a(), 10_000).unwrap();
+            set_caller(bob);
+            contract.remove_liquidity(0, liquidity / 5).unwrap();
+
+            let remaining = liquidity - liquidity / 5;
+            assert_eq!(
+                contract.account_summary(0, bob),
+                AccountSummary {
+                    lp_balance: remaining,
+                    share_bps: (remaining * 10_000 / contract.total_supply(0)) as u32,
+                    balance_0: contract.balance_of(bob, 0, token_a()),
+                    balance_1: contract.balance_of(bob, 0, token_b()),
+                    swap_count: 2,
+                }
+            );
+            // Bob keeps 200_000 of a supply reduced to 1_200_000, a sixth of it.
+            assert_eq!(contract.account_summary(0, bob).share_bps, 1_666);
+            assert!(contract.balance_of(bob, 0, token_a()) < 250_000);
+            assert_eq!(
+                contract.account_summary(0, accounts().eve),
+                AccountSummary {
+                    lp_balance: 0,
+                    share_bps: 0,
+                    balance_0: 0,
+                    balance_1: 0,
+                    swap_count: 0,
+                }
+            );
+        }
+
+        #[ink::test]
+        fn reentrant_swap_from_token_hook_is_blocked() {
+            let mut contract = setup();
+            let (alice, bob) = (accounts().alice, accounts().bob);
+            let liquidity = add_liquidity(&mut contract, alice, 1_000_000, 1_000_000).unwrap();
+
+            // A malicious token re-enters `swap` from every transfer it makes.
+            let reentries = Rc::new(RefCell::new(Vec::new()));
+            let recorded = reentries.clone();
+            mock::set_transfer_hook(move |_, _, _, _| {
+                mock::mint(token_a(), bob, 1_000);
+                let result = reentrant_handle().swap(0, token_a(), 1_000, 0, NO_DEADLINE);
+                recorded.borrow_mut().push(result);
+            });
+
+            swap(&mut contract, bob, token_a(), 10_000).unwrap();
+            add_liquidity(&mut contract, bob, 10_000, 10_000).unwrap();
+            set_caller(alice);
+            contract.remove_liquidity(0, liquidity / 2).unwrap();
+
+            // Two transfers for each of the swap, the deposit and the withdrawal.
+            let reentries = reentries.borrow();
+            assert_eq!(reentries.len(), 6);
+            assert!(reentries
+                .iter()
+                .all(|result| *result == Err(Error::Reentrancy)));
+        }
+
+        #[ink::test]
+        fn reentrancy_lock_is_released_on_error() {
+            let mut contract = setup();
+            let bob = accounts().bob;
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 1_000_000).unwrap();
+            mock::mint(token_a(), bob, 10_000);
+            set_caller(bob);
+
+            assert_eq!(
+                contract.swap(0, token_a(), 10_000, Balance::MAX, NO_DEADLINE),
+                Err(Error::SlippageExceeded)
+            );
+            assert!(!contract.locked.get().unwrap_or_default());
+            assert!(contract.swap(0, token_a(), 10_000, 0, NO_DEADLINE).is_ok());
+        }
+
+        #[ink::test]
+        fn pool_key_ignores_argument_order_and_keys_the_registry() {
+            let contract = setup();
+            let token_c = AccountId::from([0xD0; 32]);
+            let key = SimpleContract::derive_pool_key(token_a(), token_b());
+
+            assert_eq!(key, SimpleContract::derive_pool_key(token_b(), token_a()));
+            assert_eq!(contract.pool_ids.get(key), Some(0));
+            assert_ne!(key, SimpleContract::derive_pool_key(token_a(), token_c));
+            assert_ne!(
+                SimpleContract::derive_pool_key(token_a(), token_c),
+                SimpleContract::derive_pool_key(token_b(), token_c)
+            );
+        }
+
+        #[ink::test]
+        fn random_swaps_never_decrease_the_reserve_product() {
+            let mut contract = setup();
+            add_liquidity(
+                &mut contract,
+                accounts().alice,
+                1_000_000_000,
+                3_000_000_000,
+            )
+            .unwrap();
+            let product = |contract: &SimpleContract| {
+                SimpleContract::wide_mul(
+                    contract.get_reserve(0, token_a()),
+                    contract.get_reserve(0, token_b()),
+                )
+            };
+
+            // A fixed xorshift sequence picks the direction and size of each swap.
+            let mut seed: u64 = 0x9E37_79B9_7F4A_7C15;
+            let mut last = product(&contract);
+            for _ in 0..200 {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                let token_in = if seed & 1 == 0 { token_a() } else { token_b() };
+                let amount = Balance::from(seed >> 1) % 100_000_000 + 1_000;
+
+                swap(&mut contract, accounts().bob, token_in, amount).unwrap();
+                let next = product(&contract);
+                assert!(next >= last, "{amount} of {token_in:?}");
+                last = next;
+            }
+        }
+
+        #[ink::test]
+        fn token_hook_sees_the_settled_reserves_during_a_swap() {
+            let mut contract = setup();
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 1_000_000).unwrap();
+
+            let observed = Rc::new(RefCell::new(Vec::new()));
+            let recorded = observed.clone();
+            mock::set_transfer_hook(move |_, _, _, _| {
+                let handle = reentrant_handle();
+                recorded.borrow_mut().push((
+                    handle.get_reserve(0, token_a()),
+                    handle.get_reserve(0, token_b()),
+                ));
+            });
+            swap(&mut contract, accounts().bob, token_a(), 10_000).unwrap();
+
+            // Both the payment and the payout already see the post-swap reserves.
+            let settled = (
+                contract.get_reserve(0, token_a()),
+                contract.get_reserve(0, token_b()),
+            );
+            assert_ne!(settled, (1_000_000, 1_000_000));
+            assert_eq!(*observed.borrow(), vec![settled; 2]);
+        }
+
+        #[ink::test]
+        fn price_cumulative_weights_each_price_by_its_blocks() {
+            let mut contract = setup();
+            // Block 0 counts as never observed, so the pool is opened at block 1.
+            test::advance_block::<DefaultEnvironment>();
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 1_000_000).unwrap();
+            for _ in 0..10 {
+                test::advance_block::<DefaultEnvironment>();
+            }
+            swap(&mut contract, accounts().bob, token_a(), 100_000).unwrap();
+            let (price_0, price_1) = (
+                contract.get_price(0, token_a()),
+                contract.get_price(0, token_b()),
+            );
+            for _ in 0..10 {
+                test::advance_block::<DefaultEnvironment>();
+            }
+
+            assert_eq!(
+                contract.get_price_cumulative(0),
+                (
+                    10 * PRECISION + 10 * price_0,
+                    10 * PRECISION + 10 * price_1,
+                    21
+                )
+            );
+        }
+
+        #[ink::test]
+        fn twap_series_averages_each_sub_window() {
+            let mut contract = setup();
+            let advance = |blocks| {
+                for _ in 0..blocks {
+                    test::advance_block::<DefaultEnvironment>();
+                }
+            };
+            advance(1);
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 1_000_000).unwrap();
+            advance(10);
+            swap(&mut contract, accounts().bob, token_a(), 100_000).unwrap();
+            let second = contract.get_price(0, token_a());
+            advance(10);
+            swap(&mut contract, accounts().bob, token_a(), 100_000).unwrap();
+            let third = contract.get_price(0, token_a());
+            advance(10);
+
+            // Three sub-windows of 10 blocks each cover blocks 1 to 31.
+            let window = 30 * SECONDS_PER_BLOCK;
+            assert_eq!(
+                contract.get_twap_series(0, window, 3),
+                Ok(vec![PRECISION, second, third])
+            );
+            assert_eq!(
+                contract.get_twap_series(0, 2 * window, 3),
+                Err(Error::InsufficientHistory)
+            );
+            assert_eq!(
+                contract.get_twap_series(0, 2 * SECONDS_PER_BLOCK, 3),
+                Err(Error::InsufficientHistory)
+            );
+            assert_eq!(
+                contract.get_twap_series(0, window, 0),
+                Err(Error::InsufficientHistory)
+            );
+        }
+
+        /// Deploys a flash swap borrower that repays `repayment` in place of each loan.
+        fn repay_flash_loans_with(repayment: Balance) {
+            mock::set_flash_borrower(move |borrower, token_out, amount| {
+                mock::mint(token_out, borrower, repayment.saturating_sub(amount));
+                mock::transfer(token_out, borrower, AccountId::from(CONTRACT), repayment)
+                    .is_ok_and(|result| matches!(result, Ok(Ok(()))))
+            });
+        }
+
+        #[ink::test]
+        fn flash_swap_repaid_with_the_fee_keeps_the_product() {
+            let mut contract = setup();
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 1_000_000).unwrap();
+            set_caller(accounts().bob);
+
+            // 10_031 less the 0.3% fee leaves the 10_000 borrowed.
+            repay_flash_loans_with(10_031);
+            assert_eq!(
+                contract.flash_swap(0, token_b(), 10_000, accounts().django, Vec::new()),
+                Ok(())
+            );
+            assert_eq!(contract.get_reserve(0, token_a()), 1_000_000);
+            assert_eq!(contract.get_reserve(0, token_b()), 1_000_000);
+            assert_eq!(contract.get_lp_fees(0, token_b()), 31);
+            assert_eq!(contract.get_lp_fees(0, token_a()), 0);
+        }
+
+        #[ink::test]
+        fn flash_swap_rejects_short_and_missing_repayments() {
+            let mut contract = setup();
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 1_000_000).unwrap();
+            set_caller(accounts().bob);
+
+            assert_eq!(
+                contract.flash_swap(0, token_b(), 10_000, accounts().django, Vec::new()),
+                Err(Error::CallbackFailed)
+            );
+            // 10_030 less the fee is 9_999, one short of the loan.
+            repay_flash_loans_with(10_030);
+            assert_eq!(
+                contract.flash_swap(0, token_b(), 10_000, accounts().django, Vec::new()),
+                Err(Error::FlashRepaymentInsufficient)
+            );
+        }
+
+        #[ink::test]
+        fn first_deposit_locks_the_minimum_liquidity() {
+            let mut contract = setup();
+            let (alice, bob) = (accounts().alice, accounts().bob);
+
+            // sqrt(1_000_000 * 4_000_000) shares, of which the minimum is locked.
+            assert_eq!(
+                add_liquidity(&mut contract, alice, 1_000_000, 4_000_000),
+                Ok(2_000_000 - MINIMUM_LIQUIDITY)
+            );
+            assert_eq!(
+                contract.lp_balance_of(alice, 0),
+                2_000_000 - MINIMUM_LIQUIDITY
+            );
+            assert_eq!(
+                contract.lp_balance_of(AccountId::from([0; 32]), 0),
+                MINIMUM_LIQUIDITY
+            );
+            assert_eq!(contract.total_supply(0), 2_000_000);
+
+            // Later deposits mint in proportion to the supply, locked shares included.
+            assert_eq!(
+                add_liquidity(&mut contract, bob, 100_000, 400_000),
+                Ok(200_000)
+            );
+        }
+
+        #[ink::test]
+        fn optimal_counter_amount_follows_the_reserve_ratio() {
+            let mut contract = setup();
+            assert_eq!(contract.optimal_counter_amount(0, token_a(), 1_000), 0);
+
+            add_liquidity(&mut contract, accounts().alice, 2_000_000, 1_000_000).unwrap();
+            assert_eq!(contract.optimal_counter_amount(0, token_a(), 1_000), 500);
+            assert_eq!(contract.optimal_counter_amount(0, token_b(), 500), 1_000);
+        }
+
+        #[ink::test]
+        fn swap_route_chains_the_hops_and_moves_only_the_ends() {
+            let mut contract = setup();
+            let (alice, bob) = (accounts().alice, accounts().bob);
+            let token_c = AccountId::from([0xD0; 32]);
+            add_liquidity(&mut contract, alice, 1_000_000, 1_000_000).unwrap();
+            let pool_id = contract.create_pool(token_b(), token_c, FEE_BPS).unwrap();
+            mock::mint(token_b(), alice, 1_000_000);
+            mock::mint(token_c, alice, 2_000_000);
+            contract
+                .add_liquidity(pool_id, 1_000_000, 2_000_000, NO_DEADLINE)
+                .unwrap();
+
+            mock::mint(token_a(), bob, 20_000);
+            set_caller(bob);
+            let path = vec![token_a(), token_b(), token_c];
+            assert_eq!(
+                contract.swap_route(vec![token_a()], 10_000, 0, Vec::new()),
+                Err(Error::NoRoute)
+            );
+            assert_eq!(
+                contract.swap_route(
+                    vec![token_a(), AccountId::from([0xE0; 32])],
+                    10_000,
+                    0,
+                    Vec::new()
+                ),
+                Err(Error::NoRoute)
+            );
+
+            let middle = contract.quote_swap(0, token_a(), 10_000);
+            let output = contract.quote_swap(pool_id, token_b(), middle);
+            let held_b = mock::balance(token_b(), AccountId::from(CONTRACT));
+            assert_eq!(
+                contract.swap_route(path.clone(), 10_000, 0, Vec::new()),
+                Ok(output)
+            );
+            assert_eq!(mock::balance(token_c, bob), output);
+            assert_eq!(mock::balance(token_b(), bob), 0);
+            assert_eq!(mock::balance(token_b(), AccountId::from(CONTRACT)), held_b);
+
+            let next = contract.quote_swap(
+                pool_id,
+                token_b(),
+                contract.quote_swap(0, token_a(), 10_000),
+            );
+            assert_eq!(
+                contract.swap_route(path, 10_000, next + 1, Vec::new()),
+                Err(Error::SlippageExceeded)
+            );
+        }
+
+        #[ink::test]
+        fn balance_of_reads_any_account_from_any_caller() {
+            let mut contract = setup();
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 2_000_000).unwrap();
+
+            set_caller(accounts().bob);
+            assert_eq!(
+                contract.balance_of(accounts().alice, 0, token_a()),
+                1_000_000
+            );
+            assert_eq!(
+                contract.balance_of(accounts().alice, 0, token_b()),
+                2_000_000
+            );
+            assert_eq!(contract.get_balance(0, token_a()), 0);
+        }
+
+        #[ink::test]
+        fn custom_minimum_liquidity_stays_locked_after_a_full_exit() {
+            test::set_callee::<DefaultEnvironment>(AccountId::from(CONTRACT));
+            set_caller(accounts().alice);
+            let mut contract =
+                SimpleContract::new_with_minimum_liquidity(token_a(), token_b(), FEE_BPS, 5_000)
+                    .unwrap();
+
+            let liquidity =
+                add_liquidity(&mut contract, accounts().alice, 1_000_000, 1_000_000).unwrap();
+            assert_eq!(liquidity, 1_000_000 - 5_000);
+            assert_eq!(contract.lp_balance_of(AccountId::from([0; 32]), 0), 5_000);
+
+            contract.remove_liquidity(0, liquidity).unwrap();
+            assert_eq!(contract.total_supply(0), 5_000);
+            assert_eq!(contract.get_reserve(0, token_a()), 5_000);
+            assert_eq!(contract.get_reserve(0, token_b()), 5_000);
+        }
+
+        #[ink::test]
+        fn get_pool_matches_the_individual_getters() {
+            let mut contract = setup();
+            add_liquidity(&mut contract, accounts().alice, 1_000_000, 3_000_000).unwrap();
+            swap(&mut contract, accounts().bob, token_b(), 50_000).unwrap();
+
+            let pool = contract.get_pool(0).unwrap();
+            assert_eq!((pool.token_0, pool.token_1), (token_a(), token_b()));
+            assert_eq!(pool.reserve_0, contract.get_reserve(0, token_a()));
+            assert_eq!(pool.reserve_1, contract.get_reserve(0, token_b()));
+            assert_eq!(pool.total_lp_supply, contract.total_supply(0));
+            assert!(matches!(contract.get_pool(1), Err(Error::PoolNotFound)));
+        }
+
+        #[ink::test]
+        fn swaps_with_nothing_left_after_the_fee_are_rejected() {
+            let mut contract = setup();
+            add_liquidity(&mut contract, accounts().alice, 1_000, 1_000_000).unwrap();
+
+            // At 0.3%, amounts below 2 keep nothing after the fee.
+            for amount in 0..=1 {
+                assert_eq!(
+                    swap(&mut contract, accounts().bob, token_a(), amount),
+                    Err(Error::AmountTooSmall)
+                );
+            }
+            for amount in 2..=4 {
+                assert!(swap(&mut contract, accounts().bob, token_a(), amount).is_ok());
+            }
+        }
+
+        #[ink::test]
+        fn pools_store_their_tokens_in_ascending_order() {
+            test::set_callee::<DefaultEnvironment>(AccountId::from(CONTRACT));
+            set_caller(accounts().alice);
+            let mut contract = SimpleContract::new(token_b(), token_a(), FEE_BPS).unwrap();
+            let token_c = AccountId::from([0xD0; 32]);
+
+            let pool = contract.get_pool(0).unwrap();
+            assert_eq!((pool.token_0, pool.token_1), (token_a(), token_b()));
+            let pool_id = contract.create_pool(token_c, token_a(), FEE_BPS).unwrap();
+            let pool = contract.get_pool(pool_id).unwrap();
+            assert_eq!((pool.token_0, pool.token_1), (token_a(), token_c));
+            assert_eq!(
+                contract.create_pool(token_c, token_c, FEE_BPS),
+                Err(Error::IdenticalTokens)
+            );
+        }
+
+        #[ink::test]
+        fn pools_for_token_lists_every_pool_sharing_the_token() {
+            let mut contract = setup();
+            let token_c = AccountId::from([0xD0; 32]);
+            assert_eq!(contract.create_pool(token_c, token_a(), FEE_BPS), Ok(1));
+            assert_eq!(contract.create_pool(token_b(), token_c, FEE_BPS), Ok(2));
+            assert_eq!(
+                contract.create_pool(token_a(), token_c, FEE_BPS),
+                Err(Error::PoolExists { pool_id: 1 })
+            );
+
+            assert_eq!(contract.pools_for_token(token_a()), vec![0, 1]);
+            assert_eq!(contract.pools_for_token(token_b()), vec![0, 2]);
+            assert_eq!(contract.pools_for_token(token_c), vec![1, 2]);
+            assert_eq!(
+                contract.pools_for_token(AccountId::from([0xE0; 32])),
+                Vec::<PoolId>::new()
+            );
+        }
+
+        #[ink::test]
+        fn amounts_in_yield_the_target_output_along_the_route() {
+            let mut contract = setup();
+            let (alice, bob) = (accounts().alice, accounts().bob);
+            let token_c = AccountId::from([0xD0; 32]);
+            add_liquidity(&mut contract, alice, 1_000_000, 1_000_000).unwrap();
+            let pool_id = contract.create_pool(token_b(), token_c, FEE_BPS).unwrap();
+            mock::mint(token_b(), alice, 1_000_000);
+            mock::mint(token_c, alice, 2_000_000);
+            contract
+                .add_liquidity(pool_id, 1_000_000, 2_000_000, NO_DEADLINE)
+                .unwrap();
+            let path = vec![token_a(), token_b(), token_c];
+
+            let amounts = contract.get_amounts_in(path.clone(), 10_000).unwrap();
+            assert_eq!(amounts.len(), 3);
+            assert_eq!(amounts[2], 10_000);
+            // Each hop's input buys at least the input of the next one.
+            assert!(contract.quote_swap(0, token_a(), amounts[0]) >= amounts[1]);
+            assert!(contract.quote_swap(pool_id, token_b(), amounts[1]) >= amounts[2]);
+
+            mock::mint(token_a(), bob, amounts[0]);
+            set_caller(bob);
+            let output = contract
+                .swap_route(path.clone(), amounts[0], 10_000, amounts[1..].to_vec())
+                .unwrap();
+            assert!(output >= 10_000);
+            assert_eq!(mock::balance(token_a(), bob), 0);
+
+            assert_eq!(
+                contract.get_amounts_in(vec![token_a()], 10_000),
+                Err(Error::NoRoute)
+            );
+            assert_eq!(
+                contract.get_amounts_in(path.clone(), contract.get_reserve(pool_id, token_c)),
+                Err(Error::InsufficientReserve)
+            );
+            // The last pool could supply it, but not for any amount of token_b the first holds.
+            assert_eq!(
+                contract.get_amounts_in(path, 1_500_000),
+                Err(Error::InsufficientReserve)
+            );
+        }
+
+        #[ink::test]
+        fn preview_remove_all_matches_the_full_exit() {
+            let mut contract = setup();
+            let (alice, bob, charlie) = (accounts().alice, accounts().bob, accounts().charlie);
+            assert_eq!(contract.preview_remove_all(0, bob), (0, 0));
+
+            add_liquidity(&mut contract, alice, 1_000_000, 3_000_000).unwrap();
+            let liquidity = add_liquidity(&mut contract, bob, 333_333, 999_999).unwrap();
+            swap(&mut contract, charlie, token_a(), 70_000).unwrap();
+            swap(&mut contract, charlie, token_b(), 110_000).unwrap();
+
+            let preview = contract.preview_remove_all(0, bob);
+            let (fees_a, fees_b) = contract.get_unclaimed_fees(0, bob);
+            assert!(fees_a > 0 && fees_b > 0);
+
+            set_caller(bob);
+            assert_eq!(contract.remove_liquidity(0, liquidity), Ok(preview));
+            assert_eq!(contract.preview_remove_all(0, bob), (0, 0));
+        }
+
+        #[ink::test]
+        fn discrepancy_flags_surplus_until_it_is_skimmed() {
+            let mut contract = setup();
+            // The protocol keeps the whole fee.
+            contract.set_protocol_fee_bps(10_000).unwrap();
+            let (alice, eve) = (accounts().alice, accounts().eve);
+            add_liquidity(&mut contract, alice, 1_000_000, 1_000_000).unwrap();
+            swap(&mut contract, accounts().bob, token_a(), 100_000).unwrap();
+            assert_eq!(contract.get_discrepancy(token_a()), 0);
+            assert_eq!(contract.get_discrepancy(token_b()), 0);
+
+            // A donation shows up as surplus of the donated token only.
+            mock::mint(token_a(), AccountId::from(CONTRACT), 777);
+            assert_eq!(contract.get_discrepancy(token_a()), 777);
+            assert_eq!(contract.get_discrepancy(token_b()), 0);
+            set_caller(alice);
+            assert_eq!(contract.skim(token_a(), eve), Ok(777));
+            assert_eq!(contract.get_discrepancy(token_a()), 0);
+
+            // Zeroed fees stay in the contract until they are skimmed.
+            let fees = contract.get_fees(0, token_a());
+            contract.reset_fees(0, token_a()).unwrap();
+            assert_eq!(contract.get_discrepancy(token_a()), fees as i128);
+            assert_eq!(contract.skim(token_a(), eve), Ok(fees));
+            assert_eq!(contract.get_discrepancy(token_a()), 0);
+
+            // Liabilities the balance cannot cover read as negative.
+            let reserve = contract.get_reserve(0, token_b());
+            contract.set_reserve(0, token_b(), reserve + 5);
+            assert_eq!(contract.get_discrepancy(token_b()), -5);
+        }
+
+        #[ink::test]
+        fn swap_route_checks_each_hop_against_its_minimum() {
+            let mut contract = setup();
+            let (alice, bob) = (accounts().alice, accounts().bob);
+            let (token_c, token_d) = (AccountId::from([0xD0; 32]), AccountId::from([0xE0; 32]));
+            add_liquidity(&mut contract, alice, 1_000_000, 1_000_000).unwrap();
+            for (token_in, token_out) in [(token_b(), token_c), (token_c, token_d)] {
+                let pool_id = contract.create_pool(token_in, token_out, FEE_BPS).unwrap();
+                mock::mint(token_in, alice, 1_000_000);
+                mock::mint(token_out, alice, 1_000_000);
+                contract
+                    .add_liquidity(pool_id, 1_000_000, 1_000_000, NO_DEADLINE)
+                    .unwrap();
+            }
+            let path = vec![token_a(), token_b(), token_c, token_d];
+
+            mock::mint(token_a(), bob, 10_000);
+            set_caller(bob);
+            assert_eq!(
+                contract.swap_route(path.clone(), 10_000, 0, vec![0, 0]),
+                Err(Error::InvalidMinOuts)
+            );
+
+            // A floor one above the middle hop's output stops the route at that hop.
+            let first = contract.quote_swap(0, token_a(), 10_000);
+            let middle = contract.quote_swap(1, token_b(), first);
+            assert_eq!(
+                contract.swap_route(path.clone(), 10_000, 0, vec![first, middle + 1, 0]),
+                Err(Error::HopSlippageExceeded { hop: 1 })
+            );
+            assert_eq!(mock::balance(token_a(), bob), 10_000);
+            assert_eq!(mock::balance(token_d, bob), 0);
+        }
+
+        #[ink::test]
+        fn swap_route_passes_when_every_hop_meets_its_minimum() {
+            let mut contract = setup();
+            let (alice, bob) = (accounts().alice, accounts().bob);
+            let token_c = AccountId::from([0xD0; 32]);
+            add_liquidity(&mut contract, alice, 1_000_000, 1_000_000).unwrap();
+            let pool_id = contract.create_pool(token_b(), token_c, FEE_BPS).unwrap();
+            mock::mint(token_b(), alice, 1_000_000);
+            mock::mint(token_c, alice, 1_000_000);
+            contract
+                .add_liquidity(pool_id, 1_000_000, 1_000_000, NO_DEADLINE)
+                .unwrap();
+
+            mock::mint(token_a(), bob, 10_000);
+            set_caller(bob);
+            let first = contract.quote_swap(0, token_a(), 10_000);
+            let last = contract.quote_swap(pool_id, token_b(), first);
+            assert_eq!(
+                contract.swap_route(
+                    vec![token_a(), token_b(), token_c],
+                    10_000,
+                    0,
+                    vec![first, last]
+                ),
+                Ok(last)
+            );
+            assert_eq!(mock::balance(token_c, bob), last);
+        }
+    }
+
     // /// This is how you'd write end-to-end (E2E) or integration tests for ink! contracts.
     // ///
     // /// When running these you need to make sure that you: